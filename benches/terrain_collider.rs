@@ -0,0 +1,215 @@
+//! Compares building and simulating the terrain as one collider per hex
+//! against building it as a single merged `physics::terrain_shape`, on a map
+//! of the same radius as the ones that the server generates. The `contacts_*`
+//! benches include building the world, so compare them against their
+//! `construct_*` counterparts.
+//!
+//! Run with `cargo +nightly bench`.
+
+#![feature(test)]
+
+extern crate nalgebra as na;
+extern crate ncollide3d as nc;
+extern crate nphysics3d as np;
+extern crate test;
+extern crate webgl_test;
+extern crate webgl_test_common;
+
+use nc::shape::{Ball, ShapeHandle, TriMesh};
+use np::{
+    object::{BodyHandle, Material},
+    volumetric::Volumetric,
+    world::World,
+};
+use std::f32::consts::FRAC_PI_2;
+use test::Bencher;
+use webgl_test::{geometry, physics};
+use webgl_test_common::{
+    hex::OffsetCoord,
+    Hex,
+    MapData,
+    RgbByteColor,
+    SkyboxCompressed,
+    Surface,
+};
+
+const MAP_RADIUS: usize = 24;
+const BALL_COUNT: usize = 16;
+const STEPS: usize = 60;
+
+fn no_time() -> f64 {
+    0.0
+}
+
+/// A map with rolling terrain, so that plenty of hexes have exposed sides.
+fn bench_map() -> MapData {
+    let mut hexes = Vec::new();
+    for row in 0..=2 * MAP_RADIUS {
+        let row_hexes = (0..OffsetCoord::row_len(MAP_RADIUS, row))
+            .map(|col| {
+                let (x, y) =
+                    OffsetCoord::new(row, col).to_cube(MAP_RADIUS).to_pixel();
+                let height = ((x * 0.3).sin() + (y * 0.2).cos()).round() * 0.5;
+
+                Hex::new(
+                    height,
+                    RgbByteColor([0x80, 0x80, 0x80]),
+                    Surface::Grass,
+                )
+            }).collect();
+        hexes.push(row_hexes);
+    }
+
+    MapData::new(
+        MAP_RADIUS,
+        hexes,
+        0,
+        Vec::new(),
+        Vec::new(),
+        Vec::new(),
+        SkyboxCompressed::default(),
+    )
+}
+
+fn new_world() -> World<f32> {
+    let mut world = World::new(no_time);
+    world.set_gravity(na::Vector3::y() * -physics::STANDARD_GRAVITY);
+    world.set_timestep(physics::TIMESTEP);
+
+    world
+}
+
+/// The way that terrain colliders used to be built: a separate prism-shaped
+/// `TriMesh` per hex.
+fn add_per_hex_colliders(world: &mut World<f32>, map_data: &MapData) {
+    let hex_prism_verts = geometry::HEXAGONAL_PRISM_VERTS
+        .chunks(3)
+        .map(|s| na::Point3::new(s[0], s[1], s[2]))
+        .collect::<Vec<_>>();
+    let hex_prism_indices = geometry::HEXAGONAL_PRISM_INDICES
+        .chunks(3)
+        .map(|s| na::Point3::new(s[0], s[1], s[2]))
+        .collect::<Vec<_>>();
+
+    for cc in map_data.iter_coords() {
+        let hex = map_data.get_hex(cc).unwrap();
+        let (x, y) = cc.to_pixel();
+
+        world.add_collider(
+            physics::COLLIDER_MARGIN,
+            ShapeHandle::new(TriMesh::new(
+                hex_prism_verts.clone(),
+                hex_prism_indices.clone(),
+                None,
+            )),
+            BodyHandle::ground(),
+            na::Isometry3::new(
+                na::Vector3::new(x, hex.height, -y),
+                na::Vector3::new(-FRAC_PI_2, 0.0, 0.0),
+            ),
+            Material::default(),
+        );
+    }
+}
+
+fn add_merged_collider(world: &mut World<f32>, map_data: &MapData) {
+    world.add_collider(
+        physics::COLLIDER_MARGIN,
+        ShapeHandle::new(physics::terrain_shape(
+            map_data,
+            map_data.iter_coords(),
+        )),
+        BodyHandle::ground(),
+        na::Isometry3::identity(),
+        Material::default(),
+    );
+}
+
+/// Drops `BALL_COUNT` balls onto the terrain, spread out across the map, so
+/// that there is contact to be detected.
+fn add_balls(world: &mut World<f32>, map_data: &MapData) {
+    let ball = ShapeHandle::new(Ball::new(0.5));
+    let hex_count = map_data.iter_coords().count();
+    for cc in map_data
+        .iter_coords()
+        .step_by(hex_count / BALL_COUNT)
+        .take(BALL_COUNT)
+    {
+        let (x, y) = cc.to_pixel();
+        let height = map_data.get_hex(cc).unwrap().height;
+
+        let body = world.add_rigid_body(
+            na::Isometry3::new(
+                na::Vector3::new(x, height + 1.0, -y),
+                na::Vector3::zeros(),
+            ),
+            ball.inertia(1.0),
+            ball.center_of_mass(),
+        );
+        world.add_collider(
+            physics::COLLIDER_MARGIN,
+            ball.clone(),
+            body,
+            na::Isometry3::identity(),
+            Material::default(),
+        );
+    }
+}
+
+#[bench]
+fn construct_per_hex(b: &mut Bencher) {
+    let map_data = bench_map();
+
+    b.iter(|| {
+        let mut world = new_world();
+        add_per_hex_colliders(&mut world, &map_data);
+
+        world
+    });
+}
+
+#[bench]
+fn construct_merged(b: &mut Bencher) {
+    let map_data = bench_map();
+
+    b.iter(|| {
+        let mut world = new_world();
+        add_merged_collider(&mut world, &map_data);
+
+        world
+    });
+}
+
+#[bench]
+fn contacts_per_hex(b: &mut Bencher) {
+    let map_data = bench_map();
+
+    b.iter(|| {
+        let mut world = new_world();
+        add_per_hex_colliders(&mut world, &map_data);
+        add_balls(&mut world, &map_data);
+
+        for _ in 0..STEPS {
+            world.step();
+        }
+
+        world
+    });
+}
+
+#[bench]
+fn contacts_merged(b: &mut Bencher) {
+    let map_data = bench_map();
+
+    b.iter(|| {
+        let mut world = new_world();
+        add_merged_collider(&mut world, &map_data);
+        add_balls(&mut world, &map_data);
+
+        for _ in 0..STEPS {
+            world.step();
+        }
+
+        world
+    });
+}
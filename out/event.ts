@@ -43,12 +43,68 @@ export class EventQueue {
 export const KEY_DOWN = 0x01;
 export const KEY_UP = 0x02;
 export const MOUSE_MOVE = 0x03;
+export const GAMEPAD_AXES = 0x04;
+export const GAMEPAD_BUTTON_DOWN = 0x05;
+export const GAMEPAD_BUTTON_UP = 0x06;
+export const TOUCH_START = 0x07;
+export const TOUCH_MOVE = 0x08;
+export const TOUCH_END = 0x09;
+export const TOUCH_CANCEL = 0x0A;
 
+// Codes that `KeyboardEvent.code` can take on, mapped to the byte that
+// represents the key in Rust. Escape is deliberately missing, so that it can
+// always be used to unlock the pointer.
 const key_code_map = new Map([
-    ["KeyW", 0x01],
-    ["KeyA", 0x02],
-    ["KeyS", 0x03],
+    ["KeyA", 0x01],
+    ["KeyB", 0x02],
+    ["KeyC", 0x03],
     ["KeyD", 0x04],
+    ["KeyE", 0x05],
+    ["KeyF", 0x06],
+    ["KeyG", 0x07],
+    ["KeyH", 0x08],
+    ["KeyI", 0x09],
+    ["KeyJ", 0x0A],
+    ["KeyK", 0x0B],
+    ["KeyL", 0x0C],
+    ["KeyM", 0x0D],
+    ["KeyN", 0x0E],
+    ["KeyO", 0x0F],
+    ["KeyP", 0x10],
+    ["KeyQ", 0x11],
+    ["KeyR", 0x12],
+    ["KeyS", 0x13],
+    ["KeyT", 0x14],
+    ["KeyU", 0x15],
+    ["KeyV", 0x16],
+    ["KeyW", 0x17],
+    ["KeyX", 0x18],
+    ["KeyY", 0x19],
+    ["KeyZ", 0x1A],
+    ["Digit0", 0x1B],
+    ["Digit1", 0x1C],
+    ["Digit2", 0x1D],
+    ["Digit3", 0x1E],
+    ["Digit4", 0x1F],
+    ["Digit5", 0x20],
+    ["Digit6", 0x21],
+    ["Digit7", 0x22],
+    ["Digit8", 0x23],
+    ["Digit9", 0x24],
+    ["Space", 0x25],
+    ["ShiftLeft", 0x26],
+    ["ShiftRight", 0x27],
+    ["ControlLeft", 0x28],
+    ["ControlRight", 0x29],
+    ["AltLeft", 0x2A],
+    ["AltRight", 0x2B],
+    ["Tab", 0x2C],
+    ["Enter", 0x2D],
+    ["Backspace", 0x2E],
+    ["ArrowUp", 0x2F],
+    ["ArrowDown", 0x30],
+    ["ArrowLeft", 0x31],
+    ["ArrowRight", 0x32],
 ]);
 export function get_key_code(code_str: string): number | undefined {
     return key_code_map.get(code_str);
@@ -242,6 +242,22 @@ export function now(): DOMHighResTimeStamp {
     return performance.now();
 }
 
+export function local_storage_get(key: string): Uint8Array {
+    const saved = window.localStorage.getItem(key);
+    if (saved === null) {
+        return new Uint8Array(0);
+    }
+
+    return Uint8Array.from(atob(saved), c => c.charCodeAt(0));
+}
+
+export function local_storage_set(key: string, data: Uint8Array): void {
+    window.localStorage.setItem(
+        key,
+        btoa(String.fromCharCode(...Array.from(data))),
+    );
+}
+
 export function tex_image_2d_u8_sys(target:          number,
                                     level:           number,
                                     internal_format: number,
@@ -304,6 +320,7 @@ export function depth_func_sys(func: number): void {
 webgl_test.then(bg => {
     // Establish WebSocket correspondence
     const ws = new WebSocket(`ws://${location.host}/ws/`);
+    let main_loop_started = false;
     ws.binaryType = "arraybuffer";
     // Request map data
     ws.addEventListener("open", () => {
@@ -323,8 +340,11 @@ webgl_test.then(bg => {
                 throw new Error("Could not load map");
             }
 
-            // Kick off the main loop
-            window.requestAnimationFrame(main_loop);
+            // Kick off the main loop, unless this map replaced an old one
+            if (!main_loop_started) {
+                main_loop_started = true;
+                window.requestAnimationFrame(main_loop);
+            }
             break;
         default:
             log(`Unexpected opcode received: ${data[0]}`);
@@ -372,6 +392,8 @@ webgl_test.then(bg => {
             event_queue.push(
                 new Event.Event(Event.KEY_DOWN, new Uint8Array([key_code]))
             );
+        } else if (e.code === "F8") {
+            toggle_recording();
         } else if (pointer_locked && e.code === "Escape") {
             // Always allow user to unlock pointer using ESC key
             document.exitPointerLock();
@@ -394,9 +416,153 @@ webgl_test.then(bg => {
         }
     });
 
+    // Touches are sent along with the size of the canvas, which decides
+    // what each touch does. Cancelled touches are only sent by their ID
+    function push_touches(opcode: number, e: TouchEvent): void {
+        e.preventDefault();
+        const rect = canvas.getBoundingClientRect();
+        for (const touch of Array.from(e.changedTouches)) {
+            const touch_data = new ArrayBuffer(20);
+            new Uint32Array(touch_data, 0, 1)[0] = touch.identifier;
+            new Float32Array(touch_data, 4, 4).set([
+                touch.clientX - rect.left,
+                touch.clientY - rect.top,
+                rect.width,
+                rect.height,
+            ]);
+            const len = opcode === Event.TOUCH_CANCEL ? 4 : 20;
+            event_queue.push(
+                new Event.Event(opcode, new Uint8Array(touch_data, 0, len))
+            );
+        }
+    }
+    canvas.addEventListener(
+        "touchstart",
+        e => push_touches(Event.TOUCH_START, e),
+        { passive: false },
+    );
+    canvas.addEventListener(
+        "touchmove",
+        e => push_touches(Event.TOUCH_MOVE, e),
+        { passive: false },
+    );
+    canvas.addEventListener(
+        "touchend",
+        e => push_touches(Event.TOUCH_END, e),
+        { passive: false },
+    );
+    canvas.addEventListener(
+        "touchcancel",
+        e => push_touches(Event.TOUCH_CANCEL, e),
+        { passive: false },
+    );
+
+    // Recording and replaying. Recordings are downloaded as files, and can be
+    // replayed by dropping them onto the canvas
+    let recording = false;
+    function toggle_recording(): void {
+        if (!recording) {
+            recording = bg.start_recording_bg() === 0;
+            return;
+        }
+
+        recording = false;
+        const data = bg.stop_recording_bg();
+        if (data.length === 0) {
+            return;
+        }
+        const link = document.createElement("a");
+        link.href = URL.createObjectURL(new Blob([data]));
+        link.download = `recording-${Date.now()}.bin`;
+        link.click();
+        URL.revokeObjectURL(link.href);
+    }
+    canvas.addEventListener("dragover", e => e.preventDefault());
+    canvas.addEventListener("drop", e => {
+        e.preventDefault();
+        if (e.dataTransfer === null || e.dataTransfer.files.length === 0) {
+            return;
+        }
+
+        const reader = new FileReader();
+        reader.addEventListener("load", () => {
+            if (!(reader.result instanceof ArrayBuffer)) {
+                return;
+            }
+            if (bg.queue_replay_bg(new Uint8Array(reader.result)) !== 0) {
+                log("Could not read recording");
+                return;
+            }
+            recording = false;
+
+            // Ask for the map that the recording was made on; the replay
+            // starts once it has been loaded
+            const seed = bg.replay_map_seed_bg();
+            const request = new Uint8Array(9);
+            request[0] = SendOpcode.MAP_REQUEST;
+            new DataView(request.buffer).setUint32(1, seed[0], true);
+            new DataView(request.buffer).setUint32(5, seed[1], true);
+            ws.send(request);
+        });
+        reader.readAsArrayBuffer(e.dataTransfer.files[0]);
+    });
+
+    // Gamepads have no events for their sticks and buttons, so they are
+    // polled once per frame instead, and only button changes are sent
+    let gamepad_connected = false;
+    let prev_buttons: boolean[] = [];
+    function poll_gamepad(): void {
+        const gamepad = Array.from(navigator.getGamepads()).find(
+            g => g !== null && g.connected
+        );
+        if (gamepad === undefined || gamepad === null) {
+            if (gamepad_connected) {
+                // Let go of everything, so that the player doesn't keep
+                // walking or holding buttons after the gamepad is unplugged
+                event_queue.push(new Event.Event(
+                    Event.GAMEPAD_AXES,
+                    new Uint8Array(new Float32Array(4).buffer)
+                ));
+                prev_buttons.forEach((pressed, i) => {
+                    if (pressed) {
+                        event_queue.push(new Event.Event(
+                            Event.GAMEPAD_BUTTON_UP,
+                            new Uint8Array([i])
+                        ));
+                    }
+                });
+                gamepad_connected = false;
+            }
+            prev_buttons = [];
+            return;
+        }
+        gamepad_connected = true;
+
+        const axes = new Float32Array(4);
+        for (let i = 0; i < Math.min(gamepad.axes.length, 4); ++i) {
+            axes[i] = gamepad.axes[i];
+        }
+        event_queue.push(
+            new Event.Event(Event.GAMEPAD_AXES, new Uint8Array(axes.buffer))
+        );
+
+        const buttons = gamepad.buttons.map(b => b.pressed);
+        buttons.forEach((pressed, i) => {
+            if (pressed !== (prev_buttons[i] === true)) {
+                event_queue.push(new Event.Event(
+                    pressed ? Event.GAMEPAD_BUTTON_DOWN
+                            : Event.GAMEPAD_BUTTON_UP,
+                    new Uint8Array([i])
+                ));
+            }
+        });
+        prev_buttons = buttons;
+    }
+
     // Main loop
     function main_loop(t: DOMHighResTimeStamp): void {
         window.requestAnimationFrame(main_loop);
+        poll_gamepad();
         if (bg.main_loop_bg(t, event_queue) !== 0) {
             throw new Error("`main_loop_bg` failed");
         }
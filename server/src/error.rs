@@ -5,6 +5,7 @@ use std::{self, fmt, io};
 pub enum Error {
     Io(io::Error),
     ActixWeb(actix_web::error::Error),
+    MapGen(String),
 }
 
 impl fmt::Display for Error {
@@ -12,6 +13,7 @@ impl fmt::Display for Error {
         match self {
             Error::Io(ioe) => ioe.fmt(f),
             Error::ActixWeb(awe) => awe.fmt(f),
+            Error::MapGen(s) => f.write_str(s),
         }
     }
 }
@@ -32,7 +32,21 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for Ws {
                     recv_opcode::MAP_REQUEST => {
                         const MAP_RADIUS: usize = 24;
 
-                        let generated = map::generate_map(MAP_RADIUS).unwrap();
+                        // A request may ask for the map generated from a
+                        // particular seed, e.g. to replay a recording
+                        let seed = if binary.len() >= 9 {
+                            binary[1..9]
+                                .iter()
+                                .rev()
+                                .fold(0, |acc, &b| (acc << 8) | u64::from(b))
+                        } else {
+                            random::gen()
+                        };
+                        let generated = map::generate_map(
+                            MAP_RADIUS,
+                            seed,
+                            map::UnreachablePolicy::Repair,
+                        ).unwrap();
                         let mut buf =
                             Vec::with_capacity(24 * MAP_RADIUS * MAP_RADIUS);
                         {
@@ -1,121 +1,114 @@
 use error::Error;
+use rand::Rng;
 use random;
-use std::{fs, io::Read};
+use std::{f32, fs, io::Read};
 use webgl_test_common::{
+    hex::{CubeCoord, HexDir, OffsetCoord},
+    pathfind::{self, HexHeights, StepRule, Traversability},
     CompressedImgData,
     Hex,
     MapData,
+    Prop,
+    PropShape,
     RgbByteColor,
     SkyboxCompressed,
+    SpawnPoint,
+    Surface,
 };
 
-struct CubeRing {
-    cube:   Cube,
-    radius: usize,
-    i:      usize,
-    j:      usize,
+/// What `generate_map` does with maps where too large a fraction of the
+/// hexes cannot be walked to from the spawn hex and back again.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum UnreachablePolicy {
+    /// Keep the map as it was generated.
+    Allow,
+    /// Level out the cliffs that cut off stranded hexes until every hex can
+    /// be walked to and back from.
+    Repair,
+    /// Throw the map away and generate a new one, failing after
+    /// `MAX_GENERATION_ATTEMPTS` tries.
+    Reject,
 }
 
-type Cube = (isize, isize, isize);
-type Axial = (isize, isize);
-
 const STAY_PROB: f32 = 0.75;
 const STEP_SIZE: f32 = 0.5;
 const USE_PNG: bool = false;
+/// Highest step that a walking player can climb.
+const MAX_STEP_HEIGHT: f32 = STEP_SIZE;
+const MAX_STRANDED_FRACTION: f32 = 0.05;
+const MAX_GENERATION_ATTEMPTS: usize = 16;
+const SPAWN_POINT_COUNT: usize = 8;
+const ICE_PROB: f32 = 0.04;
+const MUD_PROB: f32 = 0.04;
+const BOUNCE_PAD_PROB: f32 = 0.01;
+const PROP_COUNT: usize = 12;
+/// Mass per unit volume of props. The player's body has a density of `1.0`,
+/// so props are lighter than the player and easy to push.
+const PROP_DENSITY: f32 = 0.5;
+/// Props are dropped from this high above the top of their hex.
+const PROP_DROP_HEIGHT: f32 = 0.5;
 
 #[inline]
-fn random_byte_color() -> RgbByteColor {
-    RgbByteColor(random::gen())
-}
-
-#[inline]
-fn cube_add(c0: Cube, c1: Cube) -> Cube {
-    (c0.0 + c1.0, c0.1 + c1.1, c0.2 + c1.2)
-}
-
-#[inline]
-fn cube_scale(c: Cube, a: isize) -> Cube {
-    (c.0 * a, c.1 * a, c.2 * a)
-}
-
-#[inline]
-fn cube_direction(direction: usize) -> Cube {
-    let cube_directions = [
-        (1, -1, 0),
-        (1, 0, -1),
-        (0, 1, -1),
-        (-1, 1, 0),
-        (-1, 0, 1),
-        (0, -1, 1),
-    ];
-
-    cube_directions[direction]
-}
-
-#[inline]
-fn cube_neighbor(c: Cube, direction: usize) -> Cube {
-    cube_add(c, cube_direction(direction))
+fn random_byte_color<R: Rng>(rng: &mut R) -> RgbByteColor {
+    RgbByteColor(rng.gen())
 }
 
-/// Doesn't work for `radius == 0`.
-#[inline]
-fn cube_ring(center: Cube, radius: usize) -> CubeRing {
-    CubeRing {
-        cube: cube_add(center, cube_scale(cube_direction(4), radius as isize)),
-        radius,
-        i: 0,
-        j: 0,
+/// Mostly grass, with the occasional patch of something more interesting.
+fn random_surface<R: Rng>(rng: &mut R) -> Surface {
+    let roll: f32 = rng.gen();
+    if roll < ICE_PROB {
+        Surface::Ice
+    } else if roll < ICE_PROB + MUD_PROB {
+        Surface::Mud
+    } else if roll < ICE_PROB + MUD_PROB + BOUNCE_PAD_PROB {
+        Surface::BouncePad
+    } else {
+        Surface::Grass
     }
 }
 
-impl Iterator for CubeRing {
-    type Item = Cube;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.i < 6 {
-            if self.j < self.radius {
-                self.j += 1;
-                let ret = self.cube;
-                self.cube = cube_neighbor(self.cube, self.i);
+/// Generates a map from `seed`; the same seed always generates the same map.
+pub fn generate_map(
+    radius: usize,
+    seed: u64,
+    policy: UnreachablePolicy,
+) -> Result<MapData, Error> {
+    let mut rng = random::seeded(seed);
+    let skybox = load_skybox()?;
+    let spawn = CubeCoord::map_center(radius);
+    let rule = StepRule::new(MAX_STEP_HEIGHT, f32::INFINITY, 0.0);
 
-                Some(ret)
-            } else {
-                self.j = 1;
-                self.i += 1;
-                if self.i < 6 {
-                    let ret = self.cube;
-                    self.cube = cube_neighbor(self.cube, self.i);
+    for _ in 0..MAX_GENERATION_ATTEMPTS {
+        let mut map_data = MapData::new(
+            radius,
+            generate_hexes(radius, &mut rng),
+            seed,
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            SkyboxCompressed::default(),
+        );
 
-                    Some(ret)
-                } else {
-                    None
-                }
-            }
-        } else {
-            None
+        if !enforce_policy(&mut map_data, spawn, &rule, policy) {
+            continue;
         }
-    }
-}
 
-#[inline]
-fn cube_to_axial(c: Cube) -> Axial {
-    (c.0, c.2)
-}
+        map_data.spawn_points = choose_spawn_points(&map_data, spawn, &rule);
+        map_data.props = place_props(&map_data, &mut rng);
+        map_data.skybox = skybox;
 
-#[inline]
-fn axial_to_cube(axial: Axial) -> Cube {
-    (axial.0, -(axial.0 + axial.1), axial.1)
-}
+        return Ok(map_data);
+    }
 
-#[inline]
-fn axial_to_indices(axial: Axial, radius: usize) -> (usize, usize) {
-    (
-        axial.1 as usize,
-        axial.0 as usize - radius.saturating_sub(axial.1 as usize),
-    )
+    Err(Error::MapGen(format!(
+        "Could not generate a map with at most {}% of hexes stranded after \
+         {} attempts",
+        MAX_STRANDED_FRACTION * 100.0,
+        MAX_GENERATION_ATTEMPTS,
+    )))
 }
 
-pub fn generate_map(radius: usize) -> Result<MapData, Error> {
+fn generate_hexes<R: Rng>(radius: usize, rng: &mut R) -> Vec<Vec<Hex>> {
     let (a, b) = (radius + 1, 2 * radius + 1);
     let mut hexes = Vec::with_capacity(b);
     let mut hex_parents = Vec::with_capacity(b);
@@ -133,21 +126,16 @@ pub fn generate_map(radius: usize) -> Result<MapData, Error> {
         hex_parents.push(v);
     }
 
-    hexes[radius][radius] = Hex::new(0.0, random_byte_color());
+    hexes[radius][radius] =
+        Hex::new(0.0, random_byte_color(rng), Surface::Grass);
     hex_parents[radius][radius] = (0.0, 0);
 
-    let rad = radius as isize;
-    let distance_from_center = |(c0, c1, c2): Cube| {
-        (c0 - rad)
-            .abs()
-            .max((c1 + 2 * rad).abs())
-            .max((c2 - rad).abs())
-    };
+    let center = CubeCoord::map_center(radius);
     for i in 1..=radius {
-        for c in cube_ring(axial_to_cube((rad, rad)), i) {
+        for c in center.ring(i) {
             let (mut parent0, mut parent1) = (None, None);
-            for possible_parent in cube_ring(c, 1) {
-                let possible_distance = distance_from_center(possible_parent);
+            for possible_parent in c.ring(1) {
+                let possible_distance = possible_parent.distance(center);
                 if let Some((_, p0_dist)) = parent0 {
                     if possible_distance < p0_dist {
                         parent0 = Some((possible_parent, possible_distance));
@@ -161,14 +149,14 @@ pub fn generate_map(radius: usize) -> Result<MapData, Error> {
             }
 
             let (parent_height, parent_dir) = {
-                let hex_index = |(q, _, r): Cube| {
-                    let (i, j) = axial_to_indices((q, r), radius);
-                    hex_parents[i][j]
+                let hex_index = |cc: CubeCoord| {
+                    let oc = OffsetCoord::from_cube(cc, radius).unwrap();
+                    hex_parents[oc.row][oc.col]
                 };
 
                 let (parent0, _) = parent0.unwrap();
                 if let Some(p1) = parent1 {
-                    if random::gen() {
+                    if rng.gen() {
                         hex_index(parent0)
                     } else {
                         hex_index(p1)
@@ -178,7 +166,7 @@ pub fn generate_map(radius: usize) -> Result<MapData, Error> {
                 }
             };
 
-            let our_dir = if STAY_PROB > random::gen() {
+            let our_dir = if STAY_PROB > rng.gen() {
                 parent_dir
             } else {
                 let (dir0, dir1) = match parent_dir {
@@ -187,7 +175,7 @@ pub fn generate_map(radius: usize) -> Result<MapData, Error> {
                     _ => (0, 1),
                 };
 
-                if random::gen() {
+                if rng.gen() {
                     dir0
                 } else {
                     dir1
@@ -195,12 +183,201 @@ pub fn generate_map(radius: usize) -> Result<MapData, Error> {
             };
             let our_height = parent_height + our_dir as f32 * STEP_SIZE;
 
-            let (i, j) = axial_to_indices(cube_to_axial(c), radius);
-            hexes[i][j] = Hex::new(our_height, random_byte_color());
-            hex_parents[i][j] = (our_height, our_dir);
+            let oc = OffsetCoord::from_cube(c, radius).unwrap();
+            hexes[oc.row][oc.col] = Hex::new(
+                our_height,
+                random_byte_color(rng),
+                random_surface(rng),
+            );
+            hex_parents[oc.row][oc.col] = (our_height, our_dir);
+        }
+    }
+
+    hexes
+}
+
+/// Applies `policy` to a freshly generated map, returning `false` if the map
+/// should be thrown away.
+fn enforce_policy(
+    map_data: &mut MapData,
+    spawn: CubeCoord,
+    rule: &StepRule,
+    policy: UnreachablePolicy,
+) -> bool {
+    if policy == UnreachablePolicy::Allow {
+        return true;
+    }
+
+    let traversability =
+        Traversability::analyze(map_data, map_data.iter_coords(), spawn, rule);
+    if traversability.stranded_fraction() <= MAX_STRANDED_FRACTION {
+        return true;
+    }
+
+    match policy {
+        UnreachablePolicy::Reject => false,
+        _ => {
+            repair_stranded(map_data, spawn, rule);
+
+            true
+        },
+    }
+}
+
+/// Repeatedly clamps the height of every stranded hex that borders the area
+/// that can be walked to and back from `spawn`, so that it can be stepped
+/// onto and off of, until the whole map can be walked to and back from.
+fn repair_stranded(map_data: &mut MapData, spawn: CubeCoord, rule: &StepRule) {
+    let max_step = rule.max_step_up.min(rule.max_step_down);
+
+    loop {
+        let home = pathfind::round_trip_from(map_data, spawn, rule);
+
+        let mut frontier = Vec::new();
+        for cc in map_data.iter_coords().filter(|cc| !home.contains(cc)) {
+            let height = match map_data.hex_height(cc) {
+                Some(h) => h,
+                None => continue,
+            };
+
+            let home_neighbor_height = cc
+                .neighbors()
+                .iter()
+                .filter(|n| home.contains(n))
+                .filter_map(|&n| map_data.hex_height(n))
+                .next();
+            if let Some(nh) = home_neighbor_height {
+                frontier.push((
+                    cc,
+                    height.min(nh + max_step).max(nh - max_step),
+                ));
+            }
+        }
+
+        if frontier.is_empty() {
+            break;
         }
+
+        for (cc, new_height) in frontier {
+            if let Some(hex) = map_data.get_hex_mut(cc) {
+                hex.height = new_height;
+            }
+        }
+    }
+}
+
+/// Picks up to `SPAWN_POINT_COUNT` grass hexes that are reachable from
+/// `center`, are not on the edge of the map, and are level enough with their
+/// neighbors to be walked off of in any direction. The hex closest to
+/// `center` comes first, and the rest are spread as far apart from each
+/// other as possible. Every spawn point faces towards `center`.
+fn choose_spawn_points(
+    map_data: &MapData,
+    center: CubeCoord,
+    rule: &StepRule,
+) -> Vec<SpawnPoint> {
+    let reachable = pathfind::reachable_from(map_data, center, rule);
+    let is_level_grass = |cc: CubeCoord| {
+        let height = match map_data.get_hex(cc) {
+            Some(hex) if hex.surface == Surface::Grass => hex.height,
+            _ => return false,
+        };
+
+        cc.neighbors().iter().all(|&n| {
+            map_data
+                .hex_height(n)
+                .map_or(false, |nh| (nh - height).abs() <= MAX_STEP_HEIGHT)
+        })
+    };
+
+    let mut candidates = map_data
+        .iter_coords()
+        .filter(|cc| reachable.contains(cc) && is_level_grass(*cc))
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        candidates.push(center);
+    }
+
+    let mut chosen = vec![candidates.swap_remove(0)];
+    while chosen.len() < SPAWN_POINT_COUNT && !candidates.is_empty() {
+        let (i, _) = candidates
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, &cc)| {
+                chosen.iter().map(|&s| s.distance(cc)).min()
+            }).unwrap();
+
+        chosen.push(candidates.swap_remove(i));
+    }
+
+    chosen
+        .into_iter()
+        .map(|cc| {
+            let facing = if cc == center {
+                HexDir::North
+            } else {
+                *HexDir::ALL
+                    .iter()
+                    .min_by_key(|&&dir| cc.neighbor(dir).distance(center))
+                    .unwrap()
+            };
+
+            SpawnPoint::new(cc, facing)
+        }).collect()
+}
+
+/// Scatters up to `PROP_COUNT` boxes and balls of random sizes over grass
+/// hexes, keeping clear of the spawn points and their neighbors.
+fn place_props<R: Rng>(map_data: &MapData, rng: &mut R) -> Vec<Prop> {
+    let is_near_spawn = |cc: CubeCoord| {
+        map_data
+            .spawn_points
+            .iter()
+            .any(|sp| sp.hex.distance(cc) <= 1)
+    };
+    let mut candidates = map_data
+        .iter_coords()
+        .filter(|&cc| {
+            !is_near_spawn(cc)
+                && map_data
+                    .get_hex(cc)
+                    .map_or(false, |hex| hex.surface == Surface::Grass)
+        }).collect::<Vec<_>>();
+
+    let mut props = Vec::with_capacity(PROP_COUNT);
+    while props.len() < PROP_COUNT && !candidates.is_empty() {
+        let i = rng.gen::<usize>() % candidates.len();
+        let cc = candidates.swap_remove(i);
+        let height = match map_data.hex_height(cc) {
+            Some(h) => h,
+            None => continue,
+        };
+
+        let size = 0.3 + 0.3 * rng.gen::<f32>();
+        let (shape, volume) = if rng.gen() {
+            let half_extents = [size, size, size];
+
+            (PropShape::Cuboid(half_extents), 8.0 * size * size * size)
+        } else {
+            (
+                PropShape::Ball(size),
+                4.0 / 3.0 * f32::consts::PI * size * size * size,
+            )
+        };
+        let (x, y) = cc.to_pixel();
+
+        props.push(Prop::new(
+            shape,
+            PROP_DENSITY * volume,
+            [x, height + shape.half_height() + PROP_DROP_HEIGHT, -y],
+            random_byte_color(rng),
+        ));
     }
 
+    props
+}
+
+fn load_skybox() -> Result<SkyboxCompressed, Error> {
     let mut skybox_path = "./img/skybox".to_owned();
     let mut skybox = SkyboxCompressed::default();
     for i in 0..6 {
@@ -220,5 +397,139 @@ pub fn generate_map(radius: usize) -> Result<MapData, Error> {
         };
     }
 
-    Ok(MapData::new(radius, hexes, Vec::new(), skybox))
+    Ok(skybox)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADIUS: usize = 3;
+
+    /// A flat map, apart from the outermost ring of hexes, which is at
+    /// `ring_height`.
+    fn cliff_map(ring_height: f32) -> MapData {
+        let hexes = (0..=2 * RADIUS)
+            .map(|row| {
+                (0..OffsetCoord::row_len(RADIUS, row))
+                    .map(|_| {
+                        Hex::new(
+                            0.0,
+                            RgbByteColor([0x80, 0x80, 0x80]),
+                            Surface::Grass,
+                        )
+                    }).collect()
+            }).collect();
+        let mut map_data = MapData::new(
+            RADIUS,
+            hexes,
+            0,
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            SkyboxCompressed::default(),
+        );
+        for cc in CubeCoord::map_center(RADIUS).ring(RADIUS) {
+            map_data.get_hex_mut(cc).unwrap().height = ring_height;
+        }
+
+        map_data
+    }
+
+    fn rule() -> StepRule {
+        StepRule::new(MAX_STEP_HEIGHT, f32::INFINITY, 0.0)
+    }
+
+    fn stranded_fraction(map_data: &MapData) -> f32 {
+        Traversability::analyze(
+            map_data,
+            map_data.iter_coords(),
+            CubeCoord::map_center(RADIUS),
+            &rule(),
+        ).stranded_fraction()
+    }
+
+    #[test]
+    fn drop_strands_without_being_unreachable() {
+        let map_data = cliff_map(-3.0);
+        let traversability = Traversability::analyze(
+            &map_data,
+            map_data.iter_coords(),
+            CubeCoord::map_center(RADIUS),
+            &rule(),
+        );
+
+        assert_eq!(traversability.unreachable_count(), 0);
+        assert_eq!(traversability.stranded_count(), 6 * RADIUS);
+        assert!(traversability.stranded_fraction() > MAX_STRANDED_FRACTION);
+    }
+
+    #[test]
+    fn allow_keeps_cliffs() {
+        for &ring_height in [-3.0, 3.0].iter() {
+            let mut map_data = cliff_map(ring_height);
+            let spawn = CubeCoord::map_center(RADIUS);
+
+            assert!(enforce_policy(
+                &mut map_data,
+                spawn,
+                &rule(),
+                UnreachablePolicy::Allow,
+            ));
+            assert_eq!(map_data, cliff_map(ring_height));
+        }
+    }
+
+    #[test]
+    fn reject_throws_cliffs_away() {
+        for &ring_height in [-3.0, 3.0].iter() {
+            let mut map_data = cliff_map(ring_height);
+            let spawn = CubeCoord::map_center(RADIUS);
+
+            assert!(!enforce_policy(
+                &mut map_data,
+                spawn,
+                &rule(),
+                UnreachablePolicy::Reject,
+            ));
+        }
+
+        let mut flat = cliff_map(0.0);
+        assert!(enforce_policy(
+            &mut flat,
+            CubeCoord::map_center(RADIUS),
+            &rule(),
+            UnreachablePolicy::Reject,
+        ));
+    }
+
+    #[test]
+    fn repair_levels_cliffs() {
+        for &ring_height in [-3.0, 3.0].iter() {
+            let mut map_data = cliff_map(ring_height);
+            let spawn = CubeCoord::map_center(RADIUS);
+            assert!(stranded_fraction(&map_data) > MAX_STRANDED_FRACTION);
+
+            assert!(enforce_policy(
+                &mut map_data,
+                spawn,
+                &rule(),
+                UnreachablePolicy::Repair,
+            ));
+            assert_ne!(map_data, cliff_map(ring_height));
+            assert_eq!(stranded_fraction(&map_data), 0.0);
+            for cc in CubeCoord::map_center(RADIUS).ring(RADIUS) {
+                let height = map_data.hex_height(cc).unwrap();
+                assert!(height.abs() <= MAX_STEP_HEIGHT);
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_hexes() {
+        let hexes = generate_hexes(RADIUS, &mut random::seeded(42));
+
+        assert_eq!(generate_hexes(RADIUS, &mut random::seeded(42)), hexes);
+        assert_ne!(generate_hexes(RADIUS, &mut random::seeded(43)), hexes);
+    }
 }
@@ -1,8 +1,9 @@
-use pcg_rand::Pcg32;
+use pcg_rand::{seeds::PcgSeeder, Pcg32};
 use rand::{
     distributions::{Distribution, Standard},
     FromEntropy,
     Rng,
+    SeedableRng,
 };
 use std::cell::RefCell;
 
@@ -16,3 +17,10 @@ where
 {
     RNG.with(|rng| rng.borrow_mut().gen())
 }
+
+/// A new random number generator, separate from this thread's, that always
+/// generates the same sequence of numbers from the same `seed`.
+#[inline]
+pub fn seeded(seed: u64) -> Pcg32 {
+    Pcg32::from_seed(PcgSeeder::seed(seed))
+}
@@ -0,0 +1,387 @@
+use controls::{self, Action};
+use mains;
+use na;
+use physics;
+use std::{f32::consts::PI, sync::Mutex};
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CameraMode {
+    /// Looking out from the player's center of mass.
+    FirstPerson,
+    /// Orbiting behind the player, looking in the same direction that they
+    /// are.
+    ThirdPerson,
+    /// Detached from the player, flying wherever it is steered without
+    /// colliding with anything.
+    FreeFly,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct OrbitSettings {
+    /// How far behind the point being orbited the camera sits, unless that
+    /// would put it inside of something.
+    pub distance:      f32,
+    /// How far above the player's center of mass the point being orbited is.
+    pub height_offset: f32,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FirstPersonSettings {
+    /// How far above the player's center of mass their eyes are.
+    pub eye_height: f32,
+    /// Fraction, from `0.0` up to but not including `1.0`, of the way to the
+    /// player's eyes that the camera is held back each 60th of a second.
+    /// `0.0` keeps the camera exactly at the player's eyes.
+    pub smoothing:  f32,
+    /// How far the camera bobs up and down while walking at full speed.
+    /// `0.0` turns head-bob off.
+    pub head_bob:   f32,
+}
+
+/// Where the camera is, and which way it is looking.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct CameraView {
+    pub eye:    na::Point3<f32>,
+    pub orient: na::Unit<na::Vector3<f32>>,
+}
+
+/// The camera's mode, along with where it is flying freely, how fast, and
+/// which mode it goes back to once done flying; everything about the camera
+/// that input events build on.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+pub struct ModeSnapshot {
+    pub mode:              CameraMode,
+    pub free_fly_position: [f32; 3],
+    pub free_fly_speed:    f32,
+    pub previous_mode:     CameraMode,
+}
+
+struct CameraState {
+    mode:         CameraMode,
+    orbit:        OrbitSettings,
+    free_fly:     FreeFlyState,
+    first_person: FirstPersonState,
+}
+
+struct FirstPersonState {
+    settings:   FirstPersonSettings,
+    /// Where the camera is following the player's eyes to, before head-bob,
+    /// if it has started following them yet.
+    smoothed:   Option<na::Point3<f32>>,
+    /// How far through a stride the player is, in radians.
+    bob_phase:  f32,
+    /// How much of `FirstPersonSettings::head_bob` to apply, from `0.0` to
+    /// `1.0`, eased towards how fast the player is walking.
+    bob_weight: f32,
+}
+
+struct FreeFlyState {
+    position:      na::Point3<f32>,
+    /// Flying speed, in units per second.
+    speed:         f32,
+    /// Mode to go back to once done flying.
+    previous_mode: CameraMode,
+}
+
+/// How far the third-person camera is kept from whatever it would otherwise
+/// have been pushed into.
+const COLLISION_MARGIN: f32 = 0.25;
+pub const DEFAULT_FREE_FLY_SPEED: f32 = 8.0;
+/// How many times faster the free-flying camera goes while `Action::FlyFast`
+/// is held.
+const FLY_FAST_FACTOR: f32 = 4.0;
+/// Distance walked per up-and-down cycle of head-bob.
+const HEAD_BOB_STRIDE: f32 = 1.5;
+/// How quickly, per second, head-bob fades in and out as the player speeds
+/// up and slows down.
+const HEAD_BOB_EASE_RATE: f32 = 8.0;
+/// If the player's eyes get this far away from the first-person camera at
+/// once, e.g. because they respawned, the camera jumps to them instead of
+/// following them there.
+const SNAP_DISTANCE: f32 = 2.0;
+
+lazy_static! {
+    static ref CAMERA: Mutex<CameraState> = Mutex::new(CameraState {
+        mode:     CameraMode::FirstPerson,
+        orbit:    OrbitSettings::default(),
+        free_fly: FreeFlyState {
+            position:      na::Point3::origin(),
+            speed:         DEFAULT_FREE_FLY_SPEED,
+            previous_mode: CameraMode::FirstPerson,
+        },
+        first_person: FirstPersonState {
+            settings:   FirstPersonSettings::default(),
+            smoothed:   None,
+            bob_phase:  0.0,
+            bob_weight: 0.0,
+        },
+    });
+}
+
+#[inline]
+pub fn mode() -> CameraMode {
+    CAMERA.lock().unwrap().mode
+}
+
+/// Switches to `mode`. Switching to `CameraMode::FreeFly` starts flying
+/// from wherever the camera is now.
+pub fn set_mode(mode: CameraMode) {
+    let eye = view().eye;
+
+    let mut camera = CAMERA.lock().unwrap();
+    if mode == CameraMode::FreeFly && camera.mode != CameraMode::FreeFly {
+        camera.free_fly.position = eye;
+        camera.free_fly.previous_mode = camera.mode;
+    }
+    camera.mode = mode;
+}
+
+/// Captures the camera's mode and free-flying state.
+pub fn mode_snapshot() -> ModeSnapshot {
+    let camera = CAMERA.lock().unwrap();
+    let p = camera.free_fly.position;
+
+    ModeSnapshot {
+        mode:              camera.mode,
+        free_fly_position: [p[0], p[1], p[2]],
+        free_fly_speed:    camera.free_fly.speed,
+        previous_mode:     camera.free_fly.previous_mode,
+    }
+}
+
+/// Puts the camera's mode and free-flying state back to those captured by
+/// `mode_snapshot`. Unlike `set_mode`, this doesn't move the free-flying
+/// camera to wherever the camera is now.
+pub fn restore_mode(snapshot: &ModeSnapshot) {
+    let mut camera = CAMERA.lock().unwrap();
+    let p = snapshot.free_fly_position;
+    camera.mode = snapshot.mode;
+    camera.free_fly.position = na::Point3::new(p[0], p[1], p[2]);
+    camera.free_fly.speed = snapshot.free_fly_speed;
+    camera.free_fly.previous_mode = snapshot.previous_mode;
+}
+
+/// Switches between first- and third-person. While flying freely, this
+/// changes which of the two is gone back to afterwards.
+#[inline]
+pub fn toggle_mode() {
+    let mut camera = CAMERA.lock().unwrap();
+    let camera = &mut *camera;
+    let mode = if camera.mode == CameraMode::FreeFly {
+        &mut camera.free_fly.previous_mode
+    } else {
+        &mut camera.mode
+    };
+    *mode = match *mode {
+        CameraMode::ThirdPerson => CameraMode::FirstPerson,
+        _ => CameraMode::ThirdPerson,
+    };
+}
+
+/// Starts flying freely, or goes back to the camera from before flying.
+pub fn toggle_free_fly() {
+    let mode = {
+        let camera = CAMERA.lock().unwrap();
+        match camera.mode {
+            CameraMode::FreeFly => camera.free_fly.previous_mode,
+            _ => CameraMode::FreeFly,
+        }
+    };
+
+    set_mode(mode);
+}
+
+#[inline]
+pub fn is_free_flying() -> bool {
+    mode() == CameraMode::FreeFly
+}
+
+#[inline]
+pub fn free_fly_speed() -> f32 {
+    CAMERA.lock().unwrap().free_fly.speed
+}
+
+#[inline]
+pub fn set_free_fly_speed(speed: f32) {
+    CAMERA.lock().unwrap().free_fly.speed = speed;
+}
+
+/// Flies the free-flying camera, if it is in use, according to the movement
+/// actions, over a frame that lasted `dt` seconds.
+pub fn update_free_fly(dt: f32) {
+    if !is_free_flying() {
+        return;
+    }
+
+    let orient = mains::PLAYER_STATE.lock().unwrap().orient;
+    let dir = controls::fly_direction(&orient);
+    let fast = if controls::is_active(Action::FlyFast) {
+        FLY_FAST_FACTOR
+    } else {
+        1.0
+    };
+
+    let mut camera = CAMERA.lock().unwrap();
+    let speed = camera.free_fly.speed * fast;
+    camera.free_fly.position += dir * (speed * dt);
+}
+
+#[inline]
+pub fn first_person_settings() -> FirstPersonSettings {
+    CAMERA.lock().unwrap().first_person.settings
+}
+
+#[inline]
+pub fn set_first_person_settings(settings: FirstPersonSettings) {
+    CAMERA.lock().unwrap().first_person.settings = settings;
+}
+
+/// Moves the first-person camera towards the player's eyes and bobs it along
+/// with their stride, over a frame that lasted `dt` seconds. This should be
+/// called once per frame, after physics has been advanced.
+pub fn update(dt: f32) {
+    let player_com = physics::interpolated_player_com();
+    let ground_speed = if physics::is_grounded() && !physics::is_paused() {
+        let v = physics::player_velocity();
+
+        na::Vector2::new(v[0], v[2]).norm()
+    } else {
+        0.0
+    };
+    let max_speed = physics::character_settings().max_speed;
+
+    let mut camera = CAMERA.lock().unwrap();
+    let state = &mut camera.first_person;
+    let settings = state.settings;
+
+    state.bob_phase = (state.bob_phase
+        + 2.0 * PI * ground_speed * dt / HEAD_BOB_STRIDE)
+        % (2.0 * PI);
+    let target_weight = (ground_speed / max_speed).min(1.0);
+    let ease = (HEAD_BOB_EASE_RATE * dt).min(1.0);
+    state.bob_weight += (target_weight - state.bob_weight) * ease;
+
+    let target = player_com + na::Vector3::y() * settings.eye_height;
+    state.smoothed = Some(match state.smoothed {
+        Some(smoothed) if na::distance(&smoothed, &target) < SNAP_DISTANCE =>
+            target + (smoothed - target) * settings.smoothing.powf(60.0 * dt),
+        _ => target,
+    });
+}
+
+#[inline]
+pub fn orbit_settings() -> OrbitSettings {
+    CAMERA.lock().unwrap().orbit
+}
+
+#[inline]
+pub fn set_orbit_settings(orbit: OrbitSettings) {
+    CAMERA.lock().unwrap().orbit = orbit;
+}
+
+/// Where the camera should be this frame, given where the player is and
+/// which way they are looking.
+pub fn view() -> CameraView {
+    let orient = mains::PLAYER_STATE.lock().unwrap().orient;
+    let player_com = physics::interpolated_player_com();
+    let (mode, orbit, free_fly_position, first_person_eye) = {
+        let camera = CAMERA.lock().unwrap();
+
+        (
+            camera.mode,
+            camera.orbit,
+            camera.free_fly.position,
+            camera.first_person.eye(&player_com),
+        )
+    };
+
+    let eye = match mode {
+        CameraMode::FirstPerson => first_person_eye,
+        CameraMode::FreeFly => free_fly_position,
+        CameraMode::ThirdPerson => {
+            let pivot = player_com + na::Vector3::y() * orbit.height_offset;
+            let back = -orient.unwrap();
+
+            // Pull the camera in front of anything between it and the pivot,
+            // so that it never ends up inside of a hex
+            let distance = match physics::raycast(&pivot, &back) {
+                Some(ref hit) if hit.distance < orbit.distance =>
+                    (hit.distance - COLLISION_MARGIN).max(0.0),
+                _ => orbit.distance,
+            };
+
+            pivot + back * distance
+        },
+    };
+
+    CameraView { eye, orient }
+}
+
+/// Casts a ray from the camera through the center of the screen, e.g. to
+/// find the hex that the player is looking at.
+pub fn raycast_from_camera() -> Option<physics::RaycastHit> {
+    let view = view();
+
+    physics::raycast(&view.eye, &view.orient.unwrap())
+}
+
+impl CameraView {
+    /// Matrix taking world coordinates to camera coordinates.
+    #[inline]
+    pub fn look_at(&self) -> na::Matrix4<f32> {
+        na::Matrix4::look_at_rh(
+            &self.eye,
+            &(self.eye + self.orient.unwrap()),
+            &na::Vector3::y(),
+        )
+    }
+}
+
+impl FirstPersonState {
+    /// Where the first-person camera is, given that the player's center of
+    /// mass is at `player_com`.
+    fn eye(&self, player_com: &na::Point3<f32>) -> na::Point3<f32> {
+        let smoothed = self.smoothed.unwrap_or_else(|| {
+            player_com + na::Vector3::y() * self.settings.eye_height
+        });
+        let bob =
+            self.settings.head_bob * self.bob_weight * self.bob_phase.sin();
+
+        smoothed + na::Vector3::y() * bob
+    }
+}
+
+impl FirstPersonSettings {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            eye_height: 0.6,
+            smoothing:  0.3,
+            head_bob:   0.04,
+        }
+    }
+}
+
+impl Default for FirstPersonSettings {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrbitSettings {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            distance:      4.0,
+            height_offset: 1.0,
+        }
+    }
+}
+
+impl Default for OrbitSettings {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,31 +1,195 @@
+use bincode;
 use mains;
 use na;
-use std::{f32::consts::PI, intrinsics, sync::Mutex};
+use settings;
+use std::{
+    collections::HashMap,
+    f32::consts::PI,
+    intrinsics,
+    sync::Mutex,
+};
 
+/// A key on the keyboard, identified by its position rather than by the
+/// character that it produces, as per `KeyboardEvent.code`.
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Key {
-    W = 0x01,
-    A = 0x02,
-    S = 0x03,
+    A = 0x01,
+    B = 0x02,
+    C = 0x03,
     D = 0x04,
+    E = 0x05,
+    F = 0x06,
+    G = 0x07,
+    H = 0x08,
+    I = 0x09,
+    J = 0x0A,
+    K = 0x0B,
+    L = 0x0C,
+    M = 0x0D,
+    N = 0x0E,
+    O = 0x0F,
+    P = 0x10,
+    Q = 0x11,
+    R = 0x12,
+    S = 0x13,
+    T = 0x14,
+    U = 0x15,
+    V = 0x16,
+    W = 0x17,
+    X = 0x18,
+    Y = 0x19,
+    Z = 0x1A,
+    Digit0 = 0x1B,
+    Digit1 = 0x1C,
+    Digit2 = 0x1D,
+    Digit3 = 0x1E,
+    Digit4 = 0x1F,
+    Digit5 = 0x20,
+    Digit6 = 0x21,
+    Digit7 = 0x22,
+    Digit8 = 0x23,
+    Digit9 = 0x24,
+    Space = 0x25,
+    ShiftLeft = 0x26,
+    ShiftRight = 0x27,
+    ControlLeft = 0x28,
+    ControlRight = 0x29,
+    AltLeft = 0x2A,
+    AltRight = 0x2B,
+    Tab = 0x2C,
+    Enter = 0x2D,
+    Backspace = 0x2E,
+    ArrowUp = 0x2F,
+    ArrowDown = 0x30,
+    ArrowLeft = 0x31,
+    ArrowRight = 0x32,
+}
+
+/// Something that the player can do, independently of which keys it is
+/// bound to.
+#[repr(u8)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward = 0x00,
+    MoveBackward = 0x01,
+    StrafeLeft = 0x02,
+    StrafeRight = 0x03,
+    Jump = 0x04,
+    /// Switches between first- and third-person views.
+    ToggleCamera = 0x05,
+    /// Detaches the camera from the player to fly around freely, or
+    /// reattaches it.
+    ToggleFreeFly = 0x06,
+    /// Flies straight up while flying freely.
+    FlyUp = 0x07,
+    /// Flies straight down while flying freely.
+    FlyDown = 0x08,
+    /// Flies faster while held, while flying freely.
+    FlyFast = 0x09,
+    /// Stops or restarts the physics simulation.
+    TogglePause = 0x0A,
+}
+
+/// A gamepad button, numbered as in the standard gamepad layout of the
+/// Gamepad API (e.g. `0` is the bottom face button, and `12` to `15` are the
+/// D-pad).
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GamepadButton(pub u8);
+
+/// Which keys and gamepad buttons trigger which actions. Any number of keys
+/// and buttons can be bound to the same action, but each key or button
+/// triggers at most one action.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct KeyBindings {
+    bindings: HashMap<Action, Vec<Key>>,
+    buttons:  HashMap<Action, Vec<GamepadButton>>,
+}
+
+/// How raw gamepad stick positions are turned into movement and looking.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+pub struct StickSettings {
+    /// Stick deflection, from `0.0` to `1.0`, below which the stick counts
+    /// as centered.
+    pub deadzone:   f32,
+    /// Exponent of the response curve applied to deflection beyond the
+    /// deadzone; `1.0` is linear, and higher values give finer control near
+    /// the center.
+    pub exponent:   f32,
+    /// Turning speed, in radians per second, at full deflection of the look
+    /// stick.
+    pub look_speed: f32,
+}
+
+/// A finger on the touch screen, as reported by a touch event.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TouchPoint {
+    /// Identifies the finger for as long as it stays on the screen.
+    pub id:          u32,
+    /// Position in CSS pixels, from the top left corner of the canvas.
+    pub position:    na::Point2<f32>,
+    /// Size of the canvas, in CSS pixels.
+    pub canvas_size: na::Vector2<f32>,
+}
+
+/// What a finger on the touch screen is being used for, which is decided by
+/// where it first touched the screen.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum TouchRole {
+    /// Left half of the screen: a virtual joystick centered where the finger
+    /// first touched.
+    Joystick,
+    /// Right half of the screen: dragging to look around, or tapping to jump.
+    Look,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct Touch {
+    id:     u32,
+    role:   TouchRole,
+    start:  na::Point2<f32>,
+    last:   na::Point2<f32>,
+    /// Total distance, in CSS pixels, that the finger has moved.
+    travel: f32,
+    /// Radius, in CSS pixels, of the virtual joystick.
+    radius: f32,
 }
 
 pub struct ControlState {
-    pressed: u8,
+    pressed:     u64,
+    buttons:     u32,
+    /// Raw position of the left (movement) stick, with `+x` right and `+y`
+    /// down.
+    left_stick:  na::Vector2<f32>,
+    /// Raw position of the right (look) stick, as for `left_stick`.
+    right_stick: na::Vector2<f32>,
+    /// Yaw and pitch, in radians, that the mouse has moved the view by but
+    /// that have not yet been applied, because of smoothing.
+    mouse_look:  na::Vector2<f32>,
+    touches:     Vec<Touch>,
 }
 
 pub struct PressedKeysIter {
-    pressed: u8,
+    pressed: u64,
     i:       u8,
 }
 
-const MOUSE_SENSITIVITY: f32 = 1.0 / 128.0;
 const PITCH_MARGIN: f32 = PI / 128.0;
+const DIRECTION_EPSILON: f32 = 1.0e-6;
+/// Radius of the virtual joystick, as a fraction of the shorter side of the
+/// canvas.
+const JOYSTICK_RADIUS: f32 = 0.125;
+/// Touches on the look side of the screen that move less than this many CSS
+/// pixels in total count as taps.
+const TAP_DISTANCE: f32 = 10.0;
 
 lazy_static! {
     static ref CONTROL_STATE: Mutex<ControlState> =
         Mutex::new(ControlState::new());
+    static ref KEY_BINDINGS: Mutex<KeyBindings> =
+        Mutex::new(KeyBindings::default());
+    static ref STICK_SETTINGS: Mutex<StickSettings> =
+        Mutex::new(StickSettings::default());
 }
 
 #[inline]
@@ -48,13 +212,306 @@ pub fn pressed_iter() -> PressedKeysIter {
     CONTROL_STATE.lock().unwrap().pressed_iter()
 }
 
+/// Releases every key and button, and centers the sticks.
+#[inline]
+pub fn reset() {
+    *CONTROL_STATE.lock().unwrap() = ControlState::new();
+}
+
+#[inline]
+pub fn press_button(button: GamepadButton) {
+    CONTROL_STATE.lock().unwrap().press_button(button);
+}
+
+#[inline]
+pub fn release_button(button: GamepadButton) {
+    CONTROL_STATE.lock().unwrap().release_button(button);
+}
+
+/// Sets the raw positions of the gamepad's sticks, each with `+x` right and
+/// `+y` down.
+#[inline]
+pub fn set_sticks(left: na::Vector2<f32>, right: na::Vector2<f32>) {
+    let mut control_state = CONTROL_STATE.lock().unwrap();
+    control_state.left_stick = left;
+    control_state.right_stick = right;
+}
+
+#[inline]
+pub fn stick_settings() -> StickSettings {
+    *STICK_SETTINGS.lock().unwrap()
+}
+
+#[inline]
+pub fn set_stick_settings(settings: StickSettings) {
+    *STICK_SETTINGS.lock().unwrap() = settings;
+}
+
+/// Whether any of the keys or gamepad buttons bound to `action` are
+/// pressed.
+#[inline]
+pub fn is_active(action: Action) -> bool {
+    let control_state = CONTROL_STATE.lock().unwrap();
+    let key_bindings = KEY_BINDINGS.lock().unwrap();
+
+    key_bindings
+        .keys(action)
+        .iter()
+        .any(|&k| control_state.is_pressed(k))
+        || key_bindings
+            .buttons(action)
+            .iter()
+            .any(|&b| control_state.is_button_pressed(b))
+}
+
+/// The action that `key` is bound to, if any.
+#[inline]
+pub fn action_for_key(key: Key) -> Option<Action> {
+    KEY_BINDINGS.lock().unwrap().action_for_key(key)
+}
+
+/// The action that `button` is bound to, if any.
+#[inline]
+pub fn action_for_button(button: GamepadButton) -> Option<Action> {
+    KEY_BINDINGS.lock().unwrap().action_for_button(button)
+}
+
+#[inline]
+pub fn key_bindings() -> KeyBindings {
+    KEY_BINDINGS.lock().unwrap().clone()
+}
+
+#[inline]
+pub fn set_key_bindings(bindings: KeyBindings) {
+    *KEY_BINDINGS.lock().unwrap() = bindings;
+}
+
+#[inline]
+pub fn bind_key(action: Action, key: Key) {
+    KEY_BINDINGS.lock().unwrap().bind(action, key);
+}
+
+#[inline]
+pub fn unbind_key(action: Action, key: Key) {
+    KEY_BINDINGS.lock().unwrap().unbind(action, key);
+}
+
+/// Combines all active movement actions and the movement stick into a
+/// direction of movement, relative to the direction `orient` that the player
+/// is looking in. The result lies in the horizontal plane, and is at most of
+/// unit length.
+pub fn move_direction(orient: &na::Vector3<f32>) -> na::Vector3<f32> {
+    let forward = match na::Vector3::new(orient[0], 0.0, orient[2])
+        .try_normalize(DIRECTION_EPSILON)
+    {
+        Some(f) => f,
+        None => return na::Vector3::zeros(),
+    };
+    let right = forward.cross(&na::Vector3::y());
+
+    let mut dir = na::Vector3::zeros();
+    if is_active(Action::MoveForward) {
+        dir += forward;
+    }
+    if is_active(Action::MoveBackward) {
+        dir -= forward;
+    }
+    if is_active(Action::StrafeLeft) {
+        dir -= right;
+    }
+    if is_active(Action::StrafeRight) {
+        dir += right;
+    }
+    let mut dir = dir
+        .try_normalize(DIRECTION_EPSILON)
+        .unwrap_or_else(na::Vector3::zeros);
+
+    let settings = stick_settings();
+    let control_state = CONTROL_STATE.lock().unwrap();
+    let stick = apply_response(&control_state.left_stick, &settings)
+        + control_state.joystick();
+    dir += forward * -stick[1] + right * stick[0];
+
+    if dir.norm() > 1.0 {
+        dir.normalize()
+    } else {
+        dir
+    }
+}
+
+/// Combines all active movement actions into a direction to fly in while
+/// flying freely, relative to the direction `orient` that the camera is
+/// looking in. Unlike `move_direction`, the result can point in any
+/// direction, and is at most of unit length.
+pub fn fly_direction(orient: &na::Vector3<f32>) -> na::Vector3<f32> {
+    let forward = match orient.try_normalize(DIRECTION_EPSILON) {
+        Some(f) => f,
+        None => return na::Vector3::zeros(),
+    };
+    let right = match forward
+        .cross(&na::Vector3::y())
+        .try_normalize(DIRECTION_EPSILON)
+    {
+        Some(r) => r,
+        None => return na::Vector3::zeros(),
+    };
+
+    let mut dir = na::Vector3::zeros();
+    for &(action, axis) in &[
+        (Action::MoveForward, forward),
+        (Action::MoveBackward, -forward),
+        (Action::StrafeLeft, -right),
+        (Action::StrafeRight, right),
+        (Action::FlyUp, na::Vector3::y()),
+        (Action::FlyDown, -na::Vector3::y()),
+    ] {
+        if is_active(action) {
+            dir += axis;
+        }
+    }
+    let mut dir = dir
+        .try_normalize(DIRECTION_EPSILON)
+        .unwrap_or_else(na::Vector3::zeros);
+
+    let settings = stick_settings();
+    let control_state = CONTROL_STATE.lock().unwrap();
+    let stick = apply_response(&control_state.left_stick, &settings)
+        + control_state.joystick();
+    dir += forward * -stick[1] + right * stick[0];
+
+    if dir.norm() > 1.0 {
+        dir.normalize()
+    } else {
+        dir
+    }
+}
+
+/// Queues up the view to be turned by the mouse moving `mouse_x` pixels
+/// right and `mouse_y` pixels down. The view is actually turned by
+/// `update_view`.
 pub fn handle_mouse_movement(mouse_x: f32, mouse_y: f32) {
+    let settings = settings::settings();
+    let accel = unsafe {
+        intrinsics::fadd_fast(
+            1.0,
+            settings.acceleration * mouse_x.hypot(mouse_y),
+        )
+    };
+    let pitch_sign = if settings.invert_y { -1.0 } else { 1.0 };
+
+    CONTROL_STATE.lock().unwrap().mouse_look += na::Vector2::new(
+        -settings.sensitivity_x * accel * mouse_x,
+        pitch_sign * settings.sensitivity_y * accel * mouse_y,
+    );
+}
+
+/// Starts tracking a finger that has just touched the screen.
+pub fn handle_touch_start(touch: &TouchPoint) {
+    let role = if touch.position[0] < 0.5 * touch.canvas_size[0] {
+        TouchRole::Joystick
+    } else {
+        TouchRole::Look
+    };
+    let radius =
+        JOYSTICK_RADIUS * touch.canvas_size[0].min(touch.canvas_size[1]);
+
+    let mut control_state = CONTROL_STATE.lock().unwrap();
+    control_state.touches.retain(|t| t.id != touch.id);
+    control_state.touches.push(Touch {
+        id: touch.id,
+        role,
+        start: touch.position,
+        last: touch.position,
+        travel: 0.0,
+        radius,
+    });
+}
+
+/// Moves the virtual joystick, or turns the view as the mouse would, as the
+/// finger is dragged.
+pub fn handle_touch_move(touch: &TouchPoint) {
+    let delta = {
+        let mut control_state = CONTROL_STATE.lock().unwrap();
+        let tracked = match control_state
+            .touches
+            .iter_mut()
+            .find(|t| t.id == touch.id)
+        {
+            Some(t) => t,
+            None => return,
+        };
+
+        let delta = touch.position - tracked.last;
+        tracked.last = touch.position;
+        tracked.travel += delta.norm();
+
+        if tracked.role != TouchRole::Look {
+            return;
+        }
+
+        delta
+    };
+
+    handle_mouse_movement(delta[0], delta[1]);
+}
+
+/// Stops tracking a finger that has been lifted off of the screen,
+/// returning whether it was a tap, which makes the player jump.
+pub fn handle_touch_end(touch: &TouchPoint) -> bool {
+    handle_touch_move(touch);
+
+    let mut control_state = CONTROL_STATE.lock().unwrap();
+    let i = match control_state.touches.iter().position(|t| t.id == touch.id)
+    {
+        Some(i) => i,
+        None => return false,
+    };
+    let tracked = control_state.touches.remove(i);
+
+    tracked.role == TouchRole::Look && tracked.travel < TAP_DISTANCE
+}
+
+/// Stops tracking a finger whose touch was interrupted, e.g. by the browser
+/// taking over.
+#[inline]
+pub fn handle_touch_cancel(id: u32) {
+    CONTROL_STATE.lock().unwrap().touches.retain(|t| t.id != id);
+}
+
+/// Turns the player's view according to the mouse and the look stick, over
+/// a frame that lasted `dt` seconds.
+pub fn update_view(dt: f32) {
+    let settings = settings::settings();
+    let stick_settings = stick_settings();
+    let mut control_state = CONTROL_STATE.lock().unwrap();
+
+    // Mouse movement that is held back is only delayed, never lost, so the
+    // view ends up in the same place regardless of smoothing
+    let held_back = settings.smoothing.powf(60.0 * dt);
+    let mouse_look = control_state.mouse_look * (1.0 - held_back);
+    control_state.mouse_look -= mouse_look;
+
+    let stick = apply_response(&control_state.right_stick, &stick_settings);
+    drop(control_state);
+    let pitch_sign = if settings.invert_y { -1.0 } else { 1.0 };
+    let turn = stick_settings.look_speed * dt;
+    let stick_look =
+        na::Vector2::new(-turn * stick[0], pitch_sign * turn * stick[1]);
+
+    let look = mouse_look + stick_look;
+    if look != na::zero() {
+        rotate_view(look[0], look[1]);
+    }
+}
+
+/// Turns the player's view by `yaw` radians to the left and `delta_pitch`
+/// radians downwards, stopping just short of looking straight up or down.
+fn rotate_view(yaw: f32, delta_pitch: f32) {
     let mut player_state = mains::PLAYER_STATE.lock().unwrap();
     let orient = player_state.orient.unwrap();
 
     let curr_pitch = na::Vector3::y().dot(&orient).acos();
-    let mut delta_pitch =
-        unsafe { intrinsics::fmul_fast(MOUSE_SENSITIVITY, mouse_y) };
+    let mut delta_pitch = delta_pitch;
     let new_pitch = unsafe { intrinsics::fadd_fast(curr_pitch, delta_pitch) };
     if new_pitch < PITCH_MARGIN {
         delta_pitch =
@@ -67,31 +524,286 @@ pub fn handle_mouse_movement(mouse_x: f32, mouse_y: f32) {
     player_state.orient = na::Unit::new_normalize(
         na::Rotation3::new(
             na::Vector3::y().cross(&orient).normalize() * delta_pitch,
-        ) * na::Rotation3::new(na::Vector3::new(
-            0.0,
-            unsafe { intrinsics::fmul_fast(-MOUSE_SENSITIVITY, mouse_x) },
-            0.0,
-        )) * orient,
+        ) * na::Rotation3::new(na::Vector3::new(0.0, yaw, 0.0))
+            * orient,
     );
 }
 
+/// Applies the deadzone and response curve of `settings` to the raw stick
+/// position `stick`, keeping its direction.
+fn apply_response(
+    stick: &na::Vector2<f32>,
+    settings: &StickSettings,
+) -> na::Vector2<f32> {
+    let deflection = stick.norm();
+    if deflection <= settings.deadzone {
+        return na::zero();
+    }
+
+    let beyond_deadzone = ((deflection - settings.deadzone)
+        / (1.0 - settings.deadzone))
+        .min(1.0);
+
+    stick * (beyond_deadzone.powf(settings.exponent) / deflection)
+}
+
 impl Key {
+    pub const ALL: [Key; 50] = [
+        Key::A,
+        Key::B,
+        Key::C,
+        Key::D,
+        Key::E,
+        Key::F,
+        Key::G,
+        Key::H,
+        Key::I,
+        Key::J,
+        Key::K,
+        Key::L,
+        Key::M,
+        Key::N,
+        Key::O,
+        Key::P,
+        Key::Q,
+        Key::R,
+        Key::S,
+        Key::T,
+        Key::U,
+        Key::V,
+        Key::W,
+        Key::X,
+        Key::Y,
+        Key::Z,
+        Key::Digit0,
+        Key::Digit1,
+        Key::Digit2,
+        Key::Digit3,
+        Key::Digit4,
+        Key::Digit5,
+        Key::Digit6,
+        Key::Digit7,
+        Key::Digit8,
+        Key::Digit9,
+        Key::Space,
+        Key::ShiftLeft,
+        Key::ShiftRight,
+        Key::ControlLeft,
+        Key::ControlRight,
+        Key::AltLeft,
+        Key::AltRight,
+        Key::Tab,
+        Key::Enter,
+        Key::Backspace,
+        Key::ArrowUp,
+        Key::ArrowDown,
+        Key::ArrowLeft,
+        Key::ArrowRight,
+    ];
+
     #[inline]
     pub fn from_u8(n: u8) -> Option<Self> {
-        match n {
-            n if n == Key::W as u8 => Some(Key::W),
-            n if n == Key::A as u8 => Some(Key::A),
-            n if n == Key::S as u8 => Some(Key::S),
-            n if n == Key::D as u8 => Some(Key::D),
-            _ => None,
+        // Key codes are numbered consecutively from 1
+        n.checked_sub(1)
+            .and_then(|i| Self::ALL.get(usize::from(i)))
+            .cloned()
+    }
+}
+
+impl Action {
+    pub const ALL: [Action; 11] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::StrafeLeft,
+        Action::StrafeRight,
+        Action::Jump,
+        Action::ToggleCamera,
+        Action::ToggleFreeFly,
+        Action::FlyUp,
+        Action::FlyDown,
+        Action::FlyFast,
+        Action::TogglePause,
+    ];
+
+    #[inline]
+    pub fn from_u8(n: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(n)).cloned()
+    }
+}
+
+impl KeyBindings {
+    /// No keys bound to anything.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            bindings: HashMap::new(),
+            buttons:  HashMap::new(),
         }
     }
+
+    #[inline]
+    pub fn from_raw_data(data: &[u8]) -> bincode::Result<Self> {
+        bincode::deserialize(data)
+    }
+
+    #[inline]
+    pub fn to_raw_data(&self) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(self)
+    }
+
+    #[inline]
+    pub fn keys(&self, action: Action) -> &[Key] {
+        self.bindings.get(&action).map_or(&[][..], |keys| keys.as_slice())
+    }
+
+    #[inline]
+    pub fn action_for_key(&self, key: Key) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(&action, _)| action)
+    }
+
+    /// Binds `key` to `action`, unbinding it from whichever action it was
+    /// bound to before.
+    pub fn bind(&mut self, action: Action, key: Key) {
+        for keys in self.bindings.values_mut() {
+            keys.retain(|&k| k != key);
+        }
+
+        self.bindings.entry(action).or_insert_with(Vec::new).push(key);
+    }
+
+    #[inline]
+    pub fn unbind(&mut self, action: Action, key: Key) {
+        if let Some(keys) = self.bindings.get_mut(&action) {
+            keys.retain(|&k| k != key);
+        }
+    }
+
+    #[inline]
+    pub fn buttons(&self, action: Action) -> &[GamepadButton] {
+        self.buttons.get(&action).map_or(&[][..], |bs| bs.as_slice())
+    }
+
+    #[inline]
+    pub fn action_for_button(&self, button: GamepadButton) -> Option<Action> {
+        self.buttons
+            .iter()
+            .find(|(_, bs)| bs.contains(&button))
+            .map(|(&action, _)| action)
+    }
+
+    /// Binds `button` to `action`, unbinding it from whichever action it was
+    /// bound to before.
+    pub fn bind_button(&mut self, action: Action, button: GamepadButton) {
+        for bs in self.buttons.values_mut() {
+            bs.retain(|&b| b != button);
+        }
+
+        self.buttons.entry(action).or_insert_with(Vec::new).push(button);
+    }
+
+    #[inline]
+    pub fn unbind_button(&mut self, action: Action, button: GamepadButton) {
+        if let Some(bs) = self.buttons.get_mut(&action) {
+            bs.retain(|&b| b != button);
+        }
+    }
+
+    /// Unbinds all keys and buttons from `action`.
+    #[inline]
+    pub fn clear(&mut self, action: Action) {
+        self.bindings.remove(&action);
+        self.buttons.remove(&action);
+    }
+}
+
+impl Default for KeyBindings {
+    /// WASD, the arrow keys or the D-pad to move, space or the bottom face
+    /// button to jump, and C or the top face button to switch cameras. F
+    /// flies freely, using E and Q to go up and down and shift to go faster,
+    /// and P pauses physics.
+    fn default() -> Self {
+        let mut bindings = Self::new();
+        for &(action, key) in &[
+            (Action::MoveForward, Key::W),
+            (Action::MoveForward, Key::ArrowUp),
+            (Action::MoveBackward, Key::S),
+            (Action::MoveBackward, Key::ArrowDown),
+            (Action::StrafeLeft, Key::A),
+            (Action::StrafeLeft, Key::ArrowLeft),
+            (Action::StrafeRight, Key::D),
+            (Action::StrafeRight, Key::ArrowRight),
+            (Action::Jump, Key::Space),
+            (Action::ToggleCamera, Key::C),
+            (Action::ToggleFreeFly, Key::F),
+            (Action::FlyUp, Key::E),
+            (Action::FlyDown, Key::Q),
+            (Action::FlyFast, Key::ShiftLeft),
+            (Action::TogglePause, Key::P),
+        ] {
+            bindings.bind(action, key);
+        }
+        for &(action, button) in &[
+            (Action::MoveForward, 12),
+            (Action::MoveBackward, 13),
+            (Action::StrafeLeft, 14),
+            (Action::StrafeRight, 15),
+            (Action::Jump, 0),
+            (Action::ToggleCamera, 3),
+        ] {
+            bindings.bind_button(action, GamepadButton(button));
+        }
+
+        bindings
+    }
+}
+
+impl StickSettings {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            deadzone:   0.15,
+            exponent:   2.0,
+            look_speed: PI,
+        }
+    }
+}
+
+impl Default for StickSettings {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ControlState {
     #[inline]
     pub fn new() -> Self {
-        Self { pressed: 0 }
+        Self {
+            pressed:     0,
+            buttons:     0,
+            left_stick:  na::zero(),
+            right_stick: na::zero(),
+            mouse_look:  na::zero(),
+            touches:     Vec::new(),
+        }
+    }
+
+    /// Position of the virtual joystick, as for a gamepad stick.
+    fn joystick(&self) -> na::Vector2<f32> {
+        self.touches
+            .iter()
+            .find(|t| t.role == TouchRole::Joystick)
+            .map_or_else(na::zero, |t| {
+                let offset = (t.last - t.start) / t.radius;
+                if offset.norm() > 1.0 {
+                    offset.normalize()
+                } else {
+                    offset
+                }
+            })
     }
 
     #[inline]
@@ -109,6 +821,26 @@ impl ControlState {
         self.pressed &= !(1 << key as u8);
     }
 
+    #[inline]
+    pub fn is_button_pressed(&self, button: GamepadButton) -> bool {
+        button.0 < 32 && self.buttons & (1 << button.0) != 0
+    }
+
+    /// Buttons past the 32nd are ignored.
+    #[inline]
+    pub fn press_button(&mut self, button: GamepadButton) {
+        if button.0 < 32 {
+            self.buttons |= 1 << button.0;
+        }
+    }
+
+    #[inline]
+    pub fn release_button(&mut self, button: GamepadButton) {
+        if button.0 < 32 {
+            self.buttons &= !(1 << button.0);
+        }
+    }
+
     #[inline]
     pub fn pressed_iter(&self) -> PressedKeysIter {
         PressedKeysIter {
@@ -122,10 +854,10 @@ impl Iterator for PressedKeysIter {
     type Item = Key;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while self.i < 8 && self.pressed & (1 << self.i) == 0 {
+        while self.i < 64 && self.pressed & (1 << self.i) == 0 {
             self.i += 1;
         }
-        if self.i == 8 {
+        if self.i == 64 {
             return None;
         }
 
@@ -13,6 +13,7 @@ pub enum Error {
     Logic(String),
     Bincode(bincode::Error),
     Jpeg(jpeg::Error),
+    Event(String),
 }
 
 impl fmt::Display for Error {
@@ -25,6 +26,7 @@ impl fmt::Display for Error {
             Error::Logic(s) => f.write_str(s),
             Error::Bincode(bce) => bce.fmt(f),
             Error::Jpeg(je) => je.fmt(f),
+            Error::Event(s) => f.write_str(s),
         }
     }
 }
@@ -0,0 +1,313 @@
+use byteorder::{NativeEndian, ReadBytesExt};
+use controls::{GamepadButton, Key, TouchPoint};
+use error::Error;
+use js::EventType;
+use na;
+
+/// An event from the JS event queue, decoded from its opcode and payload.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum InputEvent {
+    KeyDown(Key),
+    KeyUp(Key),
+    /// Movement of the mouse, in pixels, with `+x` right and `+y` down.
+    MouseMove(na::Vector2<f32>),
+    /// Positions of the left and right sticks, as for `MouseMove`.
+    GamepadAxes(na::Vector2<f32>, na::Vector2<f32>),
+    GamepadButtonDown(GamepadButton),
+    GamepadButtonUp(GamepadButton),
+    TouchStart(TouchPoint),
+    TouchMove(TouchPoint),
+    TouchEnd(TouchPoint),
+    /// ID of the touch that was interrupted.
+    TouchCancel(u32),
+}
+
+const KEY_PAYLOAD_LEN: usize = 1;
+const MOUSE_MOVE_PAYLOAD_LEN: usize = 8;
+const GAMEPAD_AXES_PAYLOAD_LEN: usize = 16;
+const GAMEPAD_BUTTON_PAYLOAD_LEN: usize = 1;
+const TOUCH_PAYLOAD_LEN: usize = 20;
+/// A cancelled touch is only identified, since its position is not used.
+const TOUCH_CANCEL_PAYLOAD_LEN: usize = 4;
+
+impl InputEvent {
+    /// Decodes an event, failing if the opcode is unknown or if the payload
+    /// is not what that opcode calls for.
+    pub fn decode(opcode: u8, payload: &[u8]) -> Result<Self, Error> {
+        let event_type = EventType::from_u8(opcode).ok_or_else(|| {
+            Error::Event(format!("Unknown event opcode {:#04x}", opcode))
+        })?;
+
+        let expected_len = match event_type {
+            EventType::KeyDown | EventType::KeyUp => KEY_PAYLOAD_LEN,
+            EventType::MouseMove => MOUSE_MOVE_PAYLOAD_LEN,
+            EventType::GamepadAxes => GAMEPAD_AXES_PAYLOAD_LEN,
+            EventType::GamepadButtonDown | EventType::GamepadButtonUp =>
+                GAMEPAD_BUTTON_PAYLOAD_LEN,
+            EventType::TouchStart
+            | EventType::TouchMove
+            | EventType::TouchEnd => TOUCH_PAYLOAD_LEN,
+            EventType::TouchCancel => TOUCH_CANCEL_PAYLOAD_LEN,
+        };
+        if payload.len() != expected_len {
+            return Err(Error::Event(format!(
+                "Event with opcode {:#04x} should have {} bytes of payload, \
+                 but it has {}",
+                opcode,
+                expected_len,
+                payload.len(),
+            )));
+        }
+
+        let mut payload = payload;
+        Ok(match event_type {
+            EventType::KeyDown => InputEvent::KeyDown(read_key(payload[0])?),
+            EventType::KeyUp => InputEvent::KeyUp(read_key(payload[0])?),
+            EventType::MouseMove =>
+                InputEvent::MouseMove(read_vector2(&mut payload)?),
+            EventType::GamepadAxes => InputEvent::GamepadAxes(
+                read_vector2(&mut payload)?,
+                read_vector2(&mut payload)?,
+            ),
+            EventType::GamepadButtonDown =>
+                InputEvent::GamepadButtonDown(GamepadButton(payload[0])),
+            EventType::GamepadButtonUp =>
+                InputEvent::GamepadButtonUp(GamepadButton(payload[0])),
+            EventType::TouchStart =>
+                InputEvent::TouchStart(read_touch_point(&mut payload)?),
+            EventType::TouchMove =>
+                InputEvent::TouchMove(read_touch_point(&mut payload)?),
+            EventType::TouchEnd =>
+                InputEvent::TouchEnd(read_touch_point(&mut payload)?),
+            EventType::TouchCancel => InputEvent::TouchCancel(
+                payload.read_u32::<NativeEndian>()?,
+            ),
+        })
+    }
+}
+
+#[inline]
+fn read_key(code: u8) -> Result<Key, Error> {
+    Key::from_u8(code)
+        .ok_or_else(|| Error::Event(format!("Unknown key code {:#04x}", code)))
+}
+
+fn read_vector2(payload: &mut &[u8]) -> Result<na::Vector2<f32>, Error> {
+    let x = payload.read_f32::<NativeEndian>()?;
+    let y = payload.read_f32::<NativeEndian>()?;
+    if !(x.is_finite() && y.is_finite()) {
+        return Err(Error::Event(format!(
+            "Expected finite coordinates, got ({}, {})",
+            x, y,
+        )));
+    }
+
+    Ok(na::Vector2::new(x, y))
+}
+
+/// Reads a touch's ID, its position, and the size of the canvas.
+fn read_touch_point(payload: &mut &[u8]) -> Result<TouchPoint, Error> {
+    let id = payload.read_u32::<NativeEndian>()?;
+    let position = read_vector2(payload)?;
+    let canvas_size = read_vector2(payload)?;
+    if !(canvas_size[0] > 0.0 && canvas_size[1] > 0.0) {
+        return Err(Error::Event(format!(
+            "Touch {} has an empty canvas size, {}×{}",
+            id, canvas_size[0], canvas_size[1],
+        )));
+    }
+
+    Ok(TouchPoint {
+        id,
+        position: na::Point2::new(position[0], position[1]),
+        canvas_size,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+
+    const TOUCH_ID: u32 = 7;
+
+    fn floats(fs: &[f32]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for &f in fs {
+            bytes.write_f32::<NativeEndian>(f).unwrap();
+        }
+
+        bytes
+    }
+
+    fn touch(position: [f32; 2], canvas_size: [f32; 2]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.write_u32::<NativeEndian>(TOUCH_ID).unwrap();
+        bytes.extend(floats(&[
+            position[0],
+            position[1],
+            canvas_size[0],
+            canvas_size[1],
+        ]));
+
+        bytes
+    }
+
+    fn touch_point() -> TouchPoint {
+        TouchPoint {
+            id:          TOUCH_ID,
+            position:    na::Point2::new(10.0, 20.0),
+            canvas_size: na::Vector2::new(640.0, 480.0),
+        }
+    }
+
+    /// A valid payload for each opcode, along with what it decodes to.
+    fn valid_events() -> Vec<(EventType, Vec<u8>, InputEvent)> {
+        let touch_bytes = touch([10.0, 20.0], [640.0, 480.0]);
+        let mut cancel_bytes = Vec::new();
+        cancel_bytes.write_u32::<NativeEndian>(TOUCH_ID).unwrap();
+
+        vec![
+            (EventType::KeyDown, vec![0x01], InputEvent::KeyDown(Key::A)),
+            (EventType::KeyUp, vec![0x02], InputEvent::KeyUp(Key::B)),
+            (
+                EventType::MouseMove,
+                floats(&[3.0, -4.5]),
+                InputEvent::MouseMove(na::Vector2::new(3.0, -4.5)),
+            ),
+            (
+                EventType::GamepadAxes,
+                floats(&[0.25, -0.5, 1.0, 0.0]),
+                InputEvent::GamepadAxes(
+                    na::Vector2::new(0.25, -0.5),
+                    na::Vector2::new(1.0, 0.0),
+                ),
+            ),
+            (
+                EventType::GamepadButtonDown,
+                vec![3],
+                InputEvent::GamepadButtonDown(GamepadButton(3)),
+            ),
+            (
+                EventType::GamepadButtonUp,
+                vec![12],
+                InputEvent::GamepadButtonUp(GamepadButton(12)),
+            ),
+            (
+                EventType::TouchStart,
+                touch_bytes.clone(),
+                InputEvent::TouchStart(touch_point()),
+            ),
+            (
+                EventType::TouchMove,
+                touch_bytes.clone(),
+                InputEvent::TouchMove(touch_point()),
+            ),
+            (
+                EventType::TouchEnd,
+                touch_bytes,
+                InputEvent::TouchEnd(touch_point()),
+            ),
+            (
+                EventType::TouchCancel,
+                cancel_bytes,
+                InputEvent::TouchCancel(TOUCH_ID),
+            ),
+        ]
+    }
+
+    #[test]
+    fn decodes_every_opcode() {
+        let events = valid_events();
+        assert_eq!(events.len(), 10);
+        for (event_type, payload, expected) in events {
+            assert_eq!(
+                InputEvent::decode(event_type as u8, &payload).unwrap(),
+                expected,
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_payload_lengths() {
+        for (event_type, payload, _) in valid_events() {
+            let opcode = event_type as u8;
+            assert!(InputEvent::decode(opcode, &[]).is_err());
+            assert!(
+                InputEvent::decode(opcode, &payload[..payload.len() - 1])
+                    .is_err()
+            );
+            let mut long = payload.clone();
+            long.push(0);
+            assert!(InputEvent::decode(opcode, &long).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_full_touch_payload_for_cancel() {
+        let payload = touch([10.0, 20.0], [640.0, 480.0]);
+        let opcode = EventType::TouchCancel as u8;
+        assert!(InputEvent::decode(opcode, &payload).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_opcodes() {
+        for &opcode in &[0x00, 0x0B, 0xFF] {
+            assert!(InputEvent::decode(opcode, &[0x01]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_key_codes() {
+        let unknown = Key::ALL.len() as u8 + 1;
+        for &opcode in &[EventType::KeyDown as u8, EventType::KeyUp as u8] {
+            assert!(InputEvent::decode(opcode, &[0x00]).is_err());
+            assert!(InputEvent::decode(opcode, &[unknown]).is_err());
+            assert!(InputEvent::decode(opcode, &[0xFF]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_non_finite_floats() {
+        use std::f32::{INFINITY, NAN, NEG_INFINITY};
+
+        for &bad in &[NAN, INFINITY, NEG_INFINITY] {
+            let mouse = EventType::MouseMove as u8;
+            assert!(InputEvent::decode(mouse, &floats(&[bad, 0.0])).is_err());
+            assert!(InputEvent::decode(mouse, &floats(&[0.0, bad])).is_err());
+
+            let axes = EventType::GamepadAxes as u8;
+            for i in 0..4 {
+                let mut fs = [0.0; 4];
+                fs[i] = bad;
+                assert!(InputEvent::decode(axes, &floats(&fs)).is_err());
+            }
+
+            let start = EventType::TouchStart as u8;
+            let payloads = [
+                touch([bad, 20.0], [640.0, 480.0]),
+                touch([10.0, bad], [640.0, 480.0]),
+                touch([10.0, 20.0], [bad, 480.0]),
+                touch([10.0, 20.0], [640.0, bad]),
+            ];
+            for payload in payloads.iter() {
+                assert!(InputEvent::decode(start, payload).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_empty_canvas() {
+        let sizes = [[0.0, 480.0], [640.0, 0.0], [0.0, 0.0], [-640.0, 480.0]];
+        for size in sizes.iter() {
+            for &opcode in &[
+                EventType::TouchStart as u8,
+                EventType::TouchMove as u8,
+                EventType::TouchEnd as u8,
+            ] {
+                let payload = touch([10.0, 20.0], *size);
+                assert!(InputEvent::decode(opcode, &payload).is_err());
+            }
+        }
+    }
+}
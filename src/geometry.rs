@@ -1,29 +1,23 @@
+pub use webgl_test_common::hex::{
+    axial_to_cartesian,
+    cube_direction,
+    cube_distance,
+    cube_neighbor,
+    hex_line,
+    hexes_in_range,
+    pixel_to_cube,
+    range_intersection,
+    AxialCoord,
+    CubeCoord,
+    CubeRing,
+    CubeSpiral,
+    HexDir,
+    OffsetCoord,
+    SQRT_3,
+    SQRT_3_ON_2,
+};
 use na;
-use std::intrinsics;
-
-pub type CubeCoord = na::Point3<isize>;
-
-#[derive(Clone, Debug)]
-pub struct CubeRing {
-    cube:   CubeCoord,
-    radius: usize,
-    i:      u8,
-    j:      usize,
-}
-
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
-#[repr(u8)]
-pub enum HexDir {
-    Southeast = 0,
-    Northeast = 1,
-    North = 2,
-    Northwest = 3,
-    Southwest = 4,
-    South = 5,
-}
-
-pub const SQRT_3_ON_2: f32 = 0.866_025_4;
-pub const SQRT_3: f32 = 1.732_050_8;
+use std::f32::consts::PI;
 
 #[cfg_attr(rustfmt, rustfmt_skip)]
 pub const HEXAGON: [[f32; 2]; 6] = [
@@ -35,6 +29,17 @@ pub const HEXAGON: [[f32; 2]; 6] = [
     [ 0.5, -SQRT_3_ON_2],
 ];
 
+/// The direction of the neighboring hex that shares each edge of `HEXAGON`,
+/// where edge `k` runs from corner `k` to corner `k + 1`.
+pub const HEXAGON_EDGE_DIRS: [HexDir; 6] = [
+    HexDir::Southeast,
+    HexDir::South,
+    HexDir::Southwest,
+    HexDir::Northwest,
+    HexDir::North,
+    HexDir::Northeast,
+];
+
 #[cfg_attr(rustfmt, rustfmt_skip)]
 pub const HEXAGON_VERTS: &[f32] = &[
      0.0,  0.0,
@@ -265,150 +270,110 @@ pub const CUBE_VERTICES: &[f32] = &[
      1.0, -1.0,  1.0,
 ];
 
-impl CubeRing {
-    /// Doesn't work when `radius == 0`.
-    #[inline]
-    pub fn new(center: CubeCoord, radius: usize) -> Self {
-        Self {
-            cube: center + na::Vector3::new(-1, 0, 1) * radius as isize,
-            radius,
-            i: 0,
-            j: 0,
-        }
+/// The triangles of `CUBE_VERTICES`, wound so that they face outwards rather
+/// than inwards.
+pub fn box_mesh() -> Vec<f32> {
+    let mut verts = Vec::with_capacity(CUBE_VERTICES.len());
+    for tri in CUBE_VERTICES.chunks(9) {
+        verts.extend_from_slice(&tri[0..3]);
+        verts.extend_from_slice(&tri[6..9]);
+        verts.extend_from_slice(&tri[3..6]);
     }
 
-    #[inline]
-    pub fn get_radius(&self) -> usize {
-        self.radius
-    }
+    verts
 }
 
-impl Iterator for CubeRing {
-    type Item = CubeCoord;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(i_dir) = HexDir::from_u8(self.i) {
-            if self.j < self.radius {
-                self.j += 1;
-                let ret = self.cube;
-                self.cube = cube_neighbor(self.cube, i_dir);
-
-                Some(ret)
-            } else {
-                self.j = 1;
-                self.i += 1;
-                if let Some(i_dir) = HexDir::from_u8(self.i) {
-                    let ret = self.cube;
-                    self.cube = cube_neighbor(self.cube, i_dir);
-
-                    Some(ret)
-                } else {
-                    None
-                }
+/// A sphere of radius 1, split into `rings` bands from pole to pole, each
+/// made of `segments` quads. Since the sphere is centered on the origin, the
+/// vertices double as their own normals.
+pub fn sphere_mesh(rings: usize, segments: usize) -> Vec<f32> {
+    let point = |ring: usize, segment: usize| {
+        let theta = PI * ring as f32 / rings as f32;
+        let phi = 2.0 * PI * segment as f32 / segments as f32;
+
+        [theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin()]
+    };
+
+    let mut verts = Vec::with_capacity(rings * segments * 6 * 3);
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let a = point(ring, segment);
+            let b = point(ring + 1, segment);
+            let c = point(ring + 1, segment + 1);
+            let d = point(ring, segment + 1);
+
+            for v in &[a, c, b, a, d, c] {
+                verts.extend_from_slice(v);
             }
-        } else {
-            None
         }
     }
-}
 
-impl HexDir {
-    #[inline]
-    pub fn from_u8(b: u8) -> Option<Self> {
-        match b {
-            n if n == HexDir::Southeast as u8 => Some(HexDir::Southeast),
-            n if n == HexDir::Northeast as u8 => Some(HexDir::Northeast),
-            n if n == HexDir::North as u8 => Some(HexDir::North),
-            n if n == HexDir::Northwest as u8 => Some(HexDir::Northwest),
-            n if n == HexDir::Southwest as u8 => Some(HexDir::Southwest),
-            n if n == HexDir::South as u8 => Some(HexDir::South),
-            _ => None,
-        }
-    }
+    verts
 }
 
-#[inline]
-pub fn cube_direction(dir: HexDir) -> na::Vector3<isize> {
-    match dir {
-        HexDir::Southeast => na::Vector3::new(1, -1, 0),
-        HexDir::Northeast => na::Vector3::new(1, 0, -1),
-        HexDir::North => na::Vector3::new(0, 1, -1),
-        HexDir::Northwest => na::Vector3::new(-1, 1, 0),
-        HexDir::Southwest => na::Vector3::new(-1, 0, 1),
-        HexDir::South => na::Vector3::new(0, -1, 1),
+/// An upright capsule centered on the origin, as vertices and their normals:
+/// a cylinder of the given `radius` reaching `half_height` above and below
+/// the origin, capped with hemispheres that each get half of the `rings`.
+pub fn capsule_mesh(
+    half_height: f32,
+    radius: f32,
+    rings: usize,
+    segments: usize,
+) -> (Vec<f32>, Vec<f32>) {
+    // The equator is repeated, once at the top of the cylinder and once at
+    // the bottom
+    let levels: Vec<(f32, f32)> = (0..=rings / 2)
+        .map(|ring| (ring, half_height))
+        .chain((rings / 2..=rings).map(|ring| (ring, -half_height)))
+        .map(|(ring, offset)| (PI * ring as f32 / rings as f32, offset))
+        .collect();
+    let normal = |theta: f32, segment: usize| {
+        let phi = 2.0 * PI * segment as f32 / segments as f32;
+
+        na::Vector3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    };
+
+    let vertex_count = (levels.len() - 1) * segments * 6;
+    let mut verts = Vec::with_capacity(vertex_count * 3);
+    let mut normals = Vec::with_capacity(vertex_count * 3);
+    for band in levels.windows(2) {
+        let (top, bottom) = (band[0], band[1]);
+        for segment in 0..segments {
+            let a = (top, segment);
+            let b = (bottom, segment);
+            let c = (bottom, segment + 1);
+            let d = (top, segment + 1);
+
+            for &((theta, offset), segment) in &[a, c, b, a, d, c] {
+                let n = normal(theta, segment);
+                let v = n * radius + na::Vector3::y() * offset;
+
+                verts.extend_from_slice(v.as_slice());
+                normals.extend_from_slice(n.as_slice());
+            }
+        }
     }
-}
 
-#[inline]
-pub fn cube_neighbor(cc: CubeCoord, dir: HexDir) -> CubeCoord {
-    cc + cube_direction(dir)
+    (verts, normals)
 }
 
-#[inline]
-pub fn cube_to_indices(cc: CubeCoord, radius: usize) -> (usize, usize) {
-    (
-        cc[2] as usize,
-        cc[0] as usize - radius.saturating_sub(cc[2] as usize),
-    )
-}
-
-#[inline]
-fn hex_round(cc: na::Point3<f32>) -> CubeCoord {
-    let (x, y, z) = (cc[0], cc[1], cc[2]);
-
-    let rx = x.round();
-    let ry = y.round();
-    let rz = z.round();
-
-    unsafe {
-        let x_diff = intrinsics::fsub_fast(rx, x).abs();
-        let y_diff = intrinsics::fsub_fast(ry, y).abs();
-        let z_diff = intrinsics::fsub_fast(rz, z).abs();
-
-        if x_diff > y_diff && x_diff > z_diff {
-            let (ry, rz) = (ry as isize, rz as isize);
-
-            na::Point3::new(-(ry + rz), ry, rz)
-        } else if y_diff > z_diff {
-            let (rx, rz) = (rx as isize, rz as isize);
-
-            na::Point3::new(rx, -(rx + rz), rz)
-        } else {
-            let (rx, ry) = (rx as isize, ry as isize);
-
-            na::Point3::new(rx, ry, -(rx + ry))
+/// Normals for flat-shaded triangles, one per vertex of `verts`.
+pub fn flat_normals(verts: &[f32]) -> Vec<f32> {
+    let mut normals = Vec::with_capacity(verts.len());
+    for tri in verts.chunks(9) {
+        let p0 = na::Vector3::new(tri[0], tri[1], tri[2]);
+        let p1 = na::Vector3::new(tri[3], tri[4], tri[5]);
+        let p2 = na::Vector3::new(tri[6], tri[7], tri[8]);
+        let n = (p1 - p0).cross(&(p2 - p0)).normalize();
+
+        for _ in 0..3 {
+            normals.extend_from_slice(n.as_slice());
         }
     }
-}
-
-#[inline]
-pub fn pixel_to_cube(x: f32, y: f32) -> CubeCoord {
-    const SQRT_3_ON_3: f32 = 0.577_350_26;
-    const TWO_ON_3: f32 = 2.0 / 3.0;
-    const NEG_1_ON_3: f32 = -1.0 / 3.0;
 
-    unsafe {
-        let q = intrinsics::fmul_fast(TWO_ON_3, x);
-        let r = intrinsics::fmaf32(
-            SQRT_3_ON_3,
-            y,
-            intrinsics::fmul_fast(NEG_1_ON_3, x),
-        );
-
-        hex_round(na::Point3::new(q, r, -intrinsics::fadd_fast(q, r)))
-    }
-}
-
-#[inline]
-pub fn axial_to_cartesian(q: f32, r: f32) -> (f32, f32) {
-    unsafe {
-        (
-            intrinsics::fmul_fast(1.5, q),
-            intrinsics::fmaf32(
-                SQRT_3_ON_2,
-                q,
-                intrinsics::fmul_fast(SQRT_3, r),
-            ),
-        )
-    }
+    normals
 }
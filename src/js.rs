@@ -5,6 +5,13 @@ pub enum EventType {
     KeyDown = 0x01,
     KeyUp = 0x02,
     MouseMove = 0x03,
+    GamepadAxes = 0x04,
+    GamepadButtonDown = 0x05,
+    GamepadButtonUp = 0x06,
+    TouchStart = 0x07,
+    TouchMove = 0x08,
+    TouchEnd = 0x09,
+    TouchCancel = 0x0A,
 }
 
 #[wasm_bindgen(module = "./index")]
@@ -14,6 +21,13 @@ extern "C" {
 
     /// https://developer.mozilla.org/en-US/docs/Web/API/Performance/now
     pub fn now() -> f64;
+
+    /// Bytes saved under `key` in `localStorage`, or no bytes if there is
+    /// nothing saved under `key`.
+    pub fn local_storage_get(key: &str) -> Box<[u8]>;
+
+    /// Saves `data` under `key` in `localStorage`.
+    pub fn local_storage_set(key: &str, data: &[u8]);
 }
 
 #[wasm_bindgen(module = "./event")]
@@ -66,6 +80,18 @@ impl EventType {
             n if n == EventType::KeyDown as u8 => Some(EventType::KeyDown),
             n if n == EventType::KeyUp as u8 => Some(EventType::KeyUp),
             n if n == EventType::MouseMove as u8 => Some(EventType::MouseMove),
+            n if n == EventType::GamepadAxes as u8 =>
+                Some(EventType::GamepadAxes),
+            n if n == EventType::GamepadButtonDown as u8 =>
+                Some(EventType::GamepadButtonDown),
+            n if n == EventType::GamepadButtonUp as u8 =>
+                Some(EventType::GamepadButtonUp),
+            n if n == EventType::TouchStart as u8 =>
+                Some(EventType::TouchStart),
+            n if n == EventType::TouchMove as u8 => Some(EventType::TouchMove),
+            n if n == EventType::TouchEnd as u8 => Some(EventType::TouchEnd),
+            n if n == EventType::TouchCancel as u8 =>
+                Some(EventType::TouchCancel),
             _ => None,
         }
     }
@@ -1,15 +1,19 @@
-#![feature(core_intrinsics, exact_chunks)]
+#![feature(core_intrinsics)]
 //#![allow(unused)]
 
-mod controls;
-mod error;
-mod geometry;
+pub mod camera;
+pub mod controls;
+pub mod error;
+pub mod event;
+pub mod geometry;
 mod js;
 mod mains;
-mod map;
-mod physics;
+pub mod map;
+pub mod physics;
 mod random;
+pub mod recording;
 mod render;
+pub mod settings;
 mod webgl;
 
 pub use js::*;
@@ -28,5 +32,8 @@ extern crate nphysics3d as np;
 extern crate pcg_rand;
 extern crate png;
 extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate wasm_bindgen;
 extern crate webgl_test_common;
@@ -1,11 +1,15 @@
-use byteorder::{NativeEndian, ReadBytesExt};
-use controls::{self, Key};
+use camera;
+use controls::{self, Action, Key, KeyBindings};
 use error::{log_and_return, Error};
-use js::{self, EventType};
+use event::InputEvent;
+use geometry::HexDir;
+use js;
 use map;
 use na;
 use physics;
+use recording::{self, RecordedEvent, Recording};
 use render;
+use settings::{self, Settings};
 use std::sync::Mutex;
 use wasm_bindgen::prelude::*;
 use webgl_test_common::MapData;
@@ -14,9 +18,20 @@ pub struct PlayerState {
     pub orient: na::Unit<na::Vector3<f32>>,
 }
 
+/// Time stamp, in milliseconds, of the previous call to `main_loop`.
+struct FrameClock {
+    last_time_stamp: Option<f64>,
+}
+
+const KEY_BINDINGS_STORAGE_KEY: &str = "key_bindings";
+const SETTINGS_STORAGE_KEY: &str = "settings";
+
 lazy_static! {
     pub static ref PLAYER_STATE: Mutex<PlayerState> =
         Mutex::new(PlayerState::new());
+    static ref FRAME_CLOCK: Mutex<FrameClock> = Mutex::new(FrameClock {
+        last_time_stamp: None,
+    });
 }
 
 impl PlayerState {
@@ -26,6 +41,13 @@ impl PlayerState {
             orient: na::Unit::new_unchecked(na::Vector3::new(0.0, 0.0, -1.0)),
         }
     }
+
+    /// Looks horizontally towards the neighboring hex in direction `dir`.
+    #[inline]
+    pub fn face(&mut self, dir: HexDir) {
+        let (x, y) = dir.to_cube().to_pixel();
+        self.orient = na::Unit::new_normalize(na::Vector3::new(x, 0.0, -y));
+    }
 }
 
 impl Default for PlayerState {
@@ -41,6 +63,15 @@ pub fn init_bg() -> i32 {
 
 #[inline]
 fn init() -> Result<(), Error> {
+    // Bad saved bindings shouldn't stop the game from starting, so fall back
+    // to the defaults instead
+    if let Err(e) = load_key_bindings() {
+        js::log(&format!("Could not load saved key bindings: {}", e));
+    }
+    if let Err(e) = load_settings() {
+        js::log(&format!("Could not load saved settings: {}", e));
+    }
+
     render::init()
 }
 
@@ -56,68 +87,307 @@ fn load_map(map_data: &[u8]) -> Result<(), Error> {
     let mut map_state = map::MAP.lock().unwrap();
     *map_state = map::Map::from_map_data(&map_data)?;
 
-    let radius = map_state.get_radius();
-    let (_, (x, y)) = map_state.get_hexes()[radius][radius];
-    physics::init_world(&map_state, &na::Point3::new(x, 4.0, -y));
+    let spawn_point = map_state.default_spawn_point();
+    let spawn_pos = physics::spawn_position(&map_state, &spawn_point)
+        .ok_or_else(|| {
+            Error::Logic("Spawn point is not on the map".to_owned())
+        })?;
+    PLAYER_STATE.lock().unwrap().face(spawn_point.facing);
+    physics::init_world(&map_state, &spawn_pos);
+    drop(map_state);
+
+    recording::start_pending_replay(map_data.seed)
+}
+
+#[wasm_bindgen]
+pub fn start_recording_bg() -> i32 {
+    // Not holding onto the map, since starting to record restores physics
+    let seed = map::MAP.lock().unwrap().seed;
+
+    log_and_return(recording::start_recording(seed))
+}
+
+/// The recording made since `start_recording_bg` was called, or no bytes if
+/// nothing was being recorded.
+#[wasm_bindgen]
+pub fn stop_recording_bg() -> Box<[u8]> {
+    let data = recording::stop_recording().map_or_else(
+        || Ok(Vec::new()),
+        |recording| recording.to_raw_data(),
+    );
+
+    match data {
+        Ok(data) => data.into_boxed_slice(),
+        Err(e) => {
+            js::log(&format!("Could not save recording: {}", e));
+
+            Box::new([])
+        },
+    }
+}
+
+/// Queues up a recording made by `stop_recording_bg` to be played back once
+/// the map it was made on is loaded; see `replay_map_seed_bg`.
+#[wasm_bindgen]
+pub fn queue_replay_bg(data: &[u8]) -> i32 {
+    log_and_return(
+        Recording::from_raw_data(data).map(recording::queue_replay),
+    )
+}
+
+/// Seed of the map that the queued replay needs, split into its low and high
+/// 32 bits, or an empty array if no replay is queued.
+#[wasm_bindgen]
+pub fn replay_map_seed_bg() -> Box<[u32]> {
+    match recording::pending_map_seed() {
+        Some(seed) => Box::new([seed as u32, (seed >> 32) as u32]),
+        None => Box::new([]),
+    }
+}
+
+#[wasm_bindgen]
+pub fn set_free_fly_speed_bg(speed: f32) -> i32 {
+    if !(speed > 0.0 && speed.is_finite()) {
+        return log_and_return(Err(Error::Logic(format!(
+            "Free-flying speed must be positive, got {}",
+            speed,
+        ))));
+    }
+
+    camera::set_free_fly_speed(speed);
+
+    0
+}
+
+/// Forgets when the last frame was, so that the next frame counts as taking
+/// no time at all.
+#[inline]
+pub fn reset_frame_clock() {
+    FRAME_CLOCK.lock().unwrap().last_time_stamp = None;
+}
+
+#[wasm_bindgen]
+pub fn bind_key_bg(action: u8, key: u8) -> i32 {
+    log_and_return(parse_binding(action, key).and_then(|(action, key)| {
+        controls::bind_key(action, key);
+        save_key_bindings()
+    }))
+}
+
+#[wasm_bindgen]
+pub fn unbind_key_bg(action: u8, key: u8) -> i32 {
+    log_and_return(parse_binding(action, key).and_then(|(action, key)| {
+        controls::unbind_key(action, key);
+        save_key_bindings()
+    }))
+}
+
+#[wasm_bindgen]
+pub fn reset_key_bindings_bg() -> i32 {
+    controls::set_key_bindings(KeyBindings::default());
+    log_and_return(save_key_bindings())
+}
+
+/// Restores the key bindings saved by `save_key_bindings`, if there are any.
+fn load_key_bindings() -> Result<(), Error> {
+    let data = js::local_storage_get(KEY_BINDINGS_STORAGE_KEY);
+    if !data.is_empty() {
+        controls::set_key_bindings(KeyBindings::from_raw_data(&data)?);
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn save_key_bindings() -> Result<(), Error> {
+    let data = controls::key_bindings().to_raw_data()?;
+    js::local_storage_set(KEY_BINDINGS_STORAGE_KEY, &data);
+
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn set_settings_bg(
+    sensitivity_x: f32,
+    sensitivity_y: f32,
+    invert_y: bool,
+    smoothing: f32,
+    acceleration: f32,
+    fov: f32,
+) -> i32 {
+    log_and_return(
+        settings::set_settings(Settings {
+            sensitivity_x,
+            sensitivity_y,
+            invert_y,
+            smoothing,
+            acceleration,
+            fov,
+        }).and_then(|_| save_settings()),
+    )
+}
+
+#[wasm_bindgen]
+pub fn reset_settings_bg() -> i32 {
+    log_and_return(
+        settings::set_settings(Settings::default())
+            .and_then(|_| save_settings()),
+    )
+}
+
+/// Restores the settings saved by `save_settings`, if there are any.
+fn load_settings() -> Result<(), Error> {
+    let data = js::local_storage_get(SETTINGS_STORAGE_KEY);
+    if !data.is_empty() {
+        settings::set_settings(Settings::from_raw_data(&data)?)?;
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn save_settings() -> Result<(), Error> {
+    let data = settings::settings().to_raw_data()?;
+    js::local_storage_set(SETTINGS_STORAGE_KEY, &data);
 
     Ok(())
 }
 
+#[inline]
+fn parse_binding(action: u8, key: u8) -> Result<(Action, Key), Error> {
+    let action = Action::from_u8(action).ok_or_else(|| {
+        Error::Logic(format!("There is no action with the code {}", action))
+    })?;
+    let key = Key::from_u8(key).ok_or_else(|| {
+        Error::Logic(format!("There is no key with the code {}", key))
+    })?;
+
+    Ok((action, key))
+}
+
 #[wasm_bindgen]
 pub fn main_loop_bg(time_stamp: f64, event_queue: &js::EventQueue) -> i32 {
     log_and_return(main_loop(time_stamp, event_queue))
 }
 
 fn main_loop(
-    _time_stamp: f64,
+    time_stamp: f64,
     event_queue: &js::EventQueue,
 ) -> Result<(), Error> {
-    // Handle events sent from JS "event queue"
-    for i in 0..event_queue.len() {
-        let event_data = event_queue.get(i);
-        let event_opcode = event_data.opcode();
-
-        if let Some(e) = EventType::from_u8(event_opcode) {
-            match e {
-                EventType::KeyDown =>
-                    if let Some(k) = Key::from_u8(event_data.payload()[0]) {
-                        controls::press(k);
-                    },
-                EventType::KeyUp =>
-                    if let Some(k) = Key::from_u8(event_data.payload()[0]) {
-                        controls::release(k);
-                    },
-                EventType::MouseMove => {
-                    let payload = event_data.payload();
-                    controls::handle_mouse_movement(
-                        payload.as_ref().read_f32::<NativeEndian>()?,
-                        payload[4..].as_ref().read_f32::<NativeEndian>()?,
-                    );
-                },
-            }
+    // Gather events sent from JS "event queue", unless a replay is going on,
+    // in which case its events stand in for them
+    let (time_stamp, events) = match recording::next_replay_frame() {
+        Some(frame) => (frame.time_stamp, frame.events),
+        None => (
+            time_stamp,
+            (0..event_queue.len())
+                .map(|i| {
+                    let event_data = event_queue.get(i);
+
+                    RecordedEvent {
+                        opcode:  event_data.opcode(),
+                        payload: event_data.payload().into_vec(),
+                    }
+                }).collect::<Vec<_>>(),
+        ),
+    };
+    recording::record_frame(time_stamp, &events);
+
+    // A malformed event is a bug on the JS side, but it shouldn't stop the
+    // game, so it is only logged
+    for event in &events {
+        match InputEvent::decode(event.opcode, &event.payload) {
+            Ok(e) => handle_event(e),
+            Err(e) => js::log(&format!("Dropped malformed event: {}", e)),
         }
     }
 
-    // Handle pressed keys
-    if controls::is_pressed(Key::W) {
-        let player_state = PLAYER_STATE.lock().unwrap();
-        let lin_acc = player_state.orient.unwrap() * physics::CONTROL_FORCE;
+    // Turn the view, which depends on how long the frame was
+    let frame_time = {
+        let mut frame_clock = FRAME_CLOCK.lock().unwrap();
+        let frame_time = frame_clock
+            .last_time_stamp
+            .map_or(0.0, |last| (time_stamp - last).max(0.0) / 1000.0);
+        frame_clock.last_time_stamp = Some(time_stamp);
 
-        physics::update_control_acc(lin_acc);
-    } else if controls::is_pressed(Key::S) {
-        let player_state = PLAYER_STATE.lock().unwrap();
-        let lin_acc = player_state.orient.unwrap() * -physics::CONTROL_FORCE;
+        frame_time
+    };
+    controls::update_view(frame_time as f32);
 
-        physics::update_control_acc(lin_acc);
+    // Handle pressed keys and buttons, which steer the free-flying camera
+    // instead of the player while it is in use
+    let move_dir = if camera::is_free_flying() {
+        camera::update_free_fly(frame_time as f32);
+
+        na::Vector3::zeros()
     } else {
-        physics::update_control_acc(na::Vector3::zeros());
-    }
+        controls::move_direction(&PLAYER_STATE.lock().unwrap().orient)
+    };
+    physics::set_move_direction(move_dir);
 
     // Run physics
-    physics::step();
+    physics::advance(time_stamp);
+    for respawn in physics::drain_respawn_events() {
+        PLAYER_STATE
+            .lock()
+            .unwrap()
+            .face(respawn.spawn_point.facing);
+    }
+    camera::update(frame_time as f32);
 
     // Render to screen
     render::render()?;
 
     Ok(())
 }
+
+fn handle_event(event: InputEvent) {
+    match event {
+        InputEvent::KeyDown(k) => {
+            let action = controls::action_for_key(k);
+            let was_active = action.map_or(false, controls::is_active);
+            controls::press(k);
+
+            if let (Some(action), false) = (action, was_active) {
+                start_action(action);
+            }
+        },
+        InputEvent::KeyUp(k) => controls::release(k),
+        InputEvent::MouseMove(movement) =>
+            controls::handle_mouse_movement(movement[0], movement[1]),
+        InputEvent::GamepadAxes(left, right) =>
+            controls::set_sticks(left, right),
+        InputEvent::GamepadButtonDown(b) => {
+            let action = controls::action_for_button(b);
+            let was_active = action.map_or(false, controls::is_active);
+            controls::press_button(b);
+
+            if let (Some(action), false) = (action, was_active) {
+                start_action(action);
+            }
+        },
+        InputEvent::GamepadButtonUp(b) => controls::release_button(b),
+        InputEvent::TouchStart(touch) => controls::handle_touch_start(&touch),
+        InputEvent::TouchMove(touch) => controls::handle_touch_move(&touch),
+        InputEvent::TouchEnd(touch) =>
+            if controls::handle_touch_end(&touch) {
+                start_action(Action::Jump);
+            },
+        InputEvent::TouchCancel(id) => controls::handle_touch_cancel(id),
+    }
+}
+
+/// Does whatever `action` does once, when it starts. Actions that last for
+/// as long as they are held, like movement, are handled every frame instead.
+fn start_action(action: Action) {
+    match action {
+        Action::Jump =>
+            if !camera::is_free_flying() {
+                physics::jump();
+            },
+        Action::ToggleCamera => camera::toggle_mode(),
+        Action::ToggleFreeFly => camera::toggle_free_fly(),
+        Action::TogglePause => physics::set_paused(!physics::is_paused()),
+        _ => (),
+    }
+}
@@ -1,23 +1,30 @@
 use error::Error;
-use geometry::{self, CubeCoord, CubeRing};
+use geometry::{self, CubeCoord, CubeRing, CubeSpiral, HexDir, OffsetCoord};
 use jpeg;
 use png;
-use std::{iter::Iterator, ops::Index, sync::Mutex};
+use std::{iter::Iterator, sync::Mutex};
 use webgl;
 use webgl_test_common::{
+    pathfind::{self, HexHeights, StepRule},
     CompressedImgData,
     Hex,
     LightSource,
     MapData,
+    Prop,
     RgbByteColor,
     SkyboxCompressed,
+    SpawnPoint,
+    Surface,
 };
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct Map {
     radius:            usize,
     hexes:             Vec<Vec<(Hex, (f32, f32))>>,
+    pub seed:          u64,
     pub light_sources: Vec<LightSource>,
+    pub spawn_points:  Vec<SpawnPoint>,
+    pub props:         Vec<Prop>,
     pub skybox:        Skybox,
 }
 
@@ -65,34 +72,46 @@ impl Map {
         Self {
             radius:        0,
             hexes:         Vec::new(),
+            seed:          0,
             light_sources: Vec::new(),
+            spawn_points:  Vec::new(),
+            props:         Vec::new(),
             skybox:        Skybox::default(),
         }
     }
 
     pub fn from_map_data(md: &MapData) -> Result<Self, Error> {
+        Ok(Self {
+            skybox: Skybox::from_compressed(&md.skybox)?,
+            ..Self::from_map_data_without_skybox(md)
+        })
+    }
+
+    /// Like `from_map_data`, but leaves the skybox empty instead of decoding
+    /// it, for when the map is only going to be simulated and not rendered.
+    pub fn from_map_data_without_skybox(md: &MapData) -> Self {
         let mut hexes = Vec::with_capacity(md.get_hexes().len());
         for (row_n, row) in md.get_hexes().into_iter().enumerate() {
             let mut new_row = Vec::with_capacity(row.len());
 
             for (col_n, col) in row.into_iter().enumerate() {
-                let (q, r) =
-                    (col_n + md.get_radius().saturating_sub(row_n), row_n);
-                new_row.push((
-                    col.clone(),
-                    geometry::axial_to_cartesian(q as f32, r as f32),
-                ));
+                let cc = OffsetCoord::new(row_n, col_n)
+                    .to_cube(md.get_radius());
+                new_row.push((col.clone(), cc.to_pixel()));
             }
 
             hexes.push(new_row);
         }
 
-        Ok(Self {
+        Self {
             radius: md.get_radius(),
             hexes,
+            seed: md.seed,
             light_sources: md.light_sources.clone(),
-            skybox: Skybox::from_compressed(&md.skybox)?,
-        })
+            spawn_points: md.spawn_points.clone(),
+            props: md.props.clone(),
+            skybox: Skybox::default(),
+        }
     }
 
     #[inline]
@@ -107,8 +126,14 @@ impl Map {
 
     #[inline]
     pub fn index_by_cube(&self, cc: CubeCoord) -> Option<&(Hex, (f32, f32))> {
-        let (i, j) = geometry::cube_to_indices(cc, self.radius);
-        self.hexes.get(i).and_then(|r| r.get(j))
+        let oc = OffsetCoord::from_cube(cc, self.radius)?;
+        self.hexes.get(oc.row).and_then(|r| r.get(oc.col))
+    }
+
+    /// The coordinates of every hex on the map, from the center outwards.
+    #[inline]
+    pub fn iter_coords(&self) -> CubeSpiral {
+        CubeCoord::map_center(self.radius).spiral(self.radius)
     }
 
     #[inline]
@@ -120,6 +145,94 @@ impl Map {
         }
     }
 
+    /// Whether the top of the hex at `to` can be seen from `eye_height` above
+    /// the top of the hex at `from`. Intermediate hexes block the view if
+    /// their `height` rises above the straight line between the two; hexes
+    /// that are off of the map never block.
+    pub fn line_of_sight(
+        &self,
+        from: CubeCoord,
+        to: CubeCoord,
+        eye_height: f32,
+    ) -> bool {
+        let (from_height, to_height) =
+            match (self.index_by_cube(from), self.index_by_cube(to)) {
+                (Some((from_hex, _)), Some((to_hex, _))) =>
+                    (from_hex.height + eye_height, to_hex.height),
+                _ => return false,
+            };
+
+        let line = geometry::hex_line(from, to);
+        let n = line.len() - 1;
+        for (i, cc) in line.iter().enumerate().take(n).skip(1) {
+            let t = i as f32 / n as f32;
+            let ray_height = (to_height - from_height).mul_add(t, from_height);
+
+            if let Some((hex, _)) = self.index_by_cube(*cc) {
+                if hex.height > ray_height {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// All hexes on the map within `range` steps of `center` that are
+    /// visible from `eye_height` above it, as per `line_of_sight`.
+    pub fn field_of_view(
+        &self,
+        center: CubeCoord,
+        range: usize,
+        eye_height: f32,
+    ) -> Vec<CubeCoord> {
+        geometry::hexes_in_range(center, range)
+            .into_iter()
+            .filter(|&cc| self.line_of_sight(center, cc, eye_height))
+            .collect()
+    }
+
+    /// The first of the map's spawn points, or the central hex (facing north)
+    /// if there are none.
+    #[inline]
+    pub fn default_spawn_point(&self) -> SpawnPoint {
+        self.spawn_points.first().cloned().unwrap_or_else(|| {
+            SpawnPoint::new(CubeCoord::map_center(self.radius), HexDir::North)
+        })
+    }
+
+    /// The spawn point closest to `cc`, or `default_spawn_point` if there
+    /// are none.
+    #[inline]
+    pub fn nearest_spawn_point(&self, cc: CubeCoord) -> SpawnPoint {
+        self.spawn_points
+            .iter()
+            .min_by_key(|sp| sp.hex.distance(cc))
+            .cloned()
+            .unwrap_or_else(|| self.default_spawn_point())
+    }
+
+    /// Height of the top of the lowest hex on the map, or `None` if the map
+    /// has no hexes.
+    #[inline]
+    pub fn lowest_height(&self) -> Option<f32> {
+        self.iter().map(|(hex, _)| hex.height).fold(None, |min, h| {
+            Some(min.map_or(h, |m: f32| m.min(h)))
+        })
+    }
+
+    /// Cheapest walkable path from `start` to `goal`, see
+    /// `pathfind::find_path`.
+    #[inline]
+    pub fn find_path(
+        &self,
+        start: CubeCoord,
+        goal: CubeCoord,
+        rule: &StepRule,
+    ) -> Option<Vec<CubeCoord>> {
+        pathfind::find_path(self, start, goal, rule)
+    }
+
     /// Returns an iterator over all hexes **except** the `center`, starting
     /// with the hexes closest to `center` and going further and further away.
     #[inline]
@@ -127,11 +240,18 @@ impl Map {
         MapIterRadial {
             data: &self,
             center,
-            ring: CubeRing::new(center, 1),
+            ring: center.ring(1),
         }
     }
 }
 
+impl HexHeights for Map {
+    #[inline]
+    fn hex_height(&self, cc: CubeCoord) -> Option<f32> {
+        self.index_by_cube(cc).map(|(hex, _)| hex.height)
+    }
+}
+
 impl RgbColor {
     #[inline]
     pub fn r(&self) -> f32 {
@@ -171,6 +291,29 @@ impl RgbColor {
             f32::from(bc.b()) / 255.0,
         ])
     }
+
+    /// Linearly interpolates between `self` (at `t == 0.0`) and `other` (at
+    /// `t == 1.0`).
+    #[inline]
+    pub fn mix(&self, other: &Self, t: f32) -> Self {
+        RgbColor([
+            (other.0[0] - self.0[0]).mul_add(t, self.0[0]),
+            (other.0[1] - self.0[1]).mul_add(t, self.0[1]),
+            (other.0[2] - self.0[2]).mul_add(t, self.0[2]),
+        ])
+    }
+
+    /// The color that hexes with the given `surface` are tinted towards, and
+    /// how strongly.
+    #[inline]
+    pub fn surface_tint(surface: Surface) -> (Self, f32) {
+        match surface {
+            Surface::Grass => (RgbColor([0.3, 0.6, 0.2]), 0.25),
+            Surface::Ice => (RgbColor([0.8, 0.9, 1.0]), 0.7),
+            Surface::Mud => (RgbColor([0.35, 0.25, 0.15]), 0.7),
+            Surface::BouncePad => (RgbColor([1.0, 0.3, 0.8]), 0.8),
+        }
+    }
 }
 
 impl Skybox {
@@ -360,17 +503,18 @@ impl<'a> Iterator for MapIter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         let hexes = self.data.get_hexes();
-        let h = hexes[self.row].get(self.col);
-        if h.is_some() {
-            self.col += 1;
+        while let Some(row) = hexes.get(self.row) {
+            if let Some(h) = row.get(self.col) {
+                self.col += 1;
 
-            h
-        } else {
-            self.row += 1;
-            self.col = 1;
+                return Some(h);
+            }
 
-            hexes.get(self.row).map(|r| r.index(0))
+            self.row += 1;
+            self.col = 0;
         }
+
+        None
     }
 }
 
@@ -397,3 +541,136 @@ impl<'a> Iterator for MapIterRadial<'a> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADIUS: usize = 6;
+
+    /// A map that is flat apart from any hexes given heights in `raised`.
+    fn map_with(raised: &[(CubeCoord, f32)]) -> Map {
+        let mut md = MapData::new(
+            RADIUS,
+            (0..=2 * RADIUS)
+                .map(|row| {
+                    (0..OffsetCoord::row_len(RADIUS, row))
+                        .map(|_| {
+                            Hex::new(
+                                0.0,
+                                RgbByteColor([0x80, 0x80, 0x80]),
+                                Surface::Grass,
+                            )
+                        }).collect()
+                }).collect(),
+            0,
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            SkyboxCompressed::default(),
+        );
+        for &(cc, height) in raised {
+            md.get_hex_mut(cc).unwrap().height = height;
+        }
+
+        Map::from_map_data_without_skybox(&md)
+    }
+
+    fn center() -> CubeCoord {
+        CubeCoord::map_center(RADIUS)
+    }
+
+    #[test]
+    fn flat_map_sees_everything() {
+        let map = map_with(&[]);
+        let fov = map.field_of_view(center(), RADIUS, 0.5);
+
+        assert_eq!(fov.len(), 3 * RADIUS * (RADIUS + 1) + 1);
+    }
+
+    #[test]
+    fn wall_blocks_view() {
+        let wall = center().neighbor(HexDir::North);
+        let behind = wall.neighbor(HexDir::North);
+        let map = map_with(&[(wall, 3.0)]);
+
+        assert!(!map.line_of_sight(center(), behind, 0.5));
+        assert!(!map.line_of_sight(behind, center(), 0.5));
+        // The wall itself can be seen, as can hexes off to the side
+        assert!(map.line_of_sight(center(), wall, 0.5));
+        let beside = center().neighbor(HexDir::South);
+        assert!(map.line_of_sight(center(), beside, 0.5));
+        // ...and the wall can be seen over from high enough up
+        assert!(map.line_of_sight(center(), behind, 7.0));
+    }
+
+    #[test]
+    fn off_map_has_no_line_of_sight() {
+        let map = map_with(&[]);
+        let off_map =
+            center() + HexDir::North.to_cube() * (RADIUS + 1) as isize;
+
+        assert!(!map.line_of_sight(center(), off_map, 0.5));
+        assert!(!map.line_of_sight(off_map, center(), 0.5));
+    }
+
+    #[test]
+    fn line_of_sight_uses_same_hexes_both_ways() {
+        // Raise every third hex a little, so that which hexes lie on the
+        // line matters
+        let raised = map_with(&[])
+            .iter_coords()
+            .filter(|cc| (cc.x - cc.y) % 3 == 0)
+            .map(|cc| (cc, 0.25))
+            .collect::<Vec<_>>();
+        let map = map_with(&raised);
+
+        let coords = map.iter_coords().collect::<Vec<_>>();
+        for &a in &coords {
+            for &b in &coords {
+                if map.index_by_cube(a).unwrap().0.height
+                    == map.index_by_cube(b).unwrap().0.height
+                {
+                    assert_eq!(
+                        map.line_of_sight(a, b, 0.0),
+                        map.line_of_sight(b, a, 0.0),
+                        "{:?} and {:?}",
+                        a,
+                        b,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn iter_visits_every_hex_once() {
+        let map = map_with(&[]);
+
+        assert_eq!(map.iter().count(), 3 * RADIUS * (RADIUS + 1) + 1);
+        let mut pixels = map
+            .iter()
+            .map(|&(_, (x, y))| (x.to_bits(), y.to_bits()))
+            .collect::<Vec<_>>();
+        pixels.sort();
+        pixels.dedup();
+        assert_eq!(pixels.len(), map.iter().count());
+    }
+
+    #[test]
+    fn lowest_height() {
+        let low = center().neighbor(HexDir::South);
+        let map = map_with(&[(low, -2.5), (center(), 4.0)]);
+        assert_eq!(map.lowest_height(), Some(-2.5));
+
+        assert_eq!(map_with(&[]).lowest_height(), Some(0.0));
+    }
+
+    #[test]
+    fn empty_map_has_no_lowest_height() {
+        let map = Map::from_map_data_without_skybox(&MapData::default());
+
+        assert_eq!(map.iter().count(), 0);
+        assert_eq!(map.lowest_height(), None);
+    }
+}
@@ -1,128 +1,1288 @@
-use geometry;
+use bincode;
+use error::Error;
+use geometry::{self, CubeCoord, SQRT_3_ON_2};
+#[cfg(target_arch = "wasm32")]
 use js;
 use map;
 use na;
-use nc::shape::{Cuboid, ShapeHandle, TriMesh};
+use nc::{
+    events::ContactEvent,
+    query::{Proximity, Ray},
+    shape::{Ball, Capsule, Cuboid, Cylinder, ShapeHandle, TriMesh},
+    world::CollisionGroups,
+};
 use np::{
-    force_generator::{ConstantAcceleration, ForceGeneratorHandle},
-    object::{BodyHandle, Material},
+    algebra::Velocity3,
+    object::{BodyHandle, ColliderHandle, Material, RigidBody},
     volumetric::Volumetric,
     world::World,
 };
-use std::{f32::consts::FRAC_PI_2, intrinsics, sync::Mutex};
+use std::{
+    cmp,
+    collections::HashMap,
+    f32::consts::FRAC_PI_4,
+    intrinsics,
+    sync::Mutex,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+use webgl_test_common::{
+    pathfind::HexHeights,
+    PropShape,
+    SpawnPoint,
+    Surface,
+};
 
 pub const COLLIDER_MARGIN: f32 = 0.01;
 /// In m/s^2, as defined by la Conférence générale des poids et mesures
 pub const STANDARD_GRAVITY: f32 = 9.806_65;
-pub const CONTROL_FORCE: f32 = 20.0;
+pub const PLAYER_RADIUS: f32 = 0.5;
+/// Half of the length of the segment between the centers of the two
+/// hemispherical ends of the player's capsule.
+pub const PLAYER_CAPSULE_HALF_HEIGHT: f32 = 0.5;
+/// Distance from the player's center of mass to the bottom of their feet.
+pub const PLAYER_HALF_HEIGHT: f32 = PLAYER_CAPSULE_HALF_HEIGHT + PLAYER_RADIUS;
+/// Gap left between the bottom of the player and the top of the hex that they
+/// spawn on.
+pub const SPAWN_CLEARANCE: f32 = 0.25;
+/// How far below the bottom of the player the ground may be for them to still
+/// count as standing on it.
+pub const GROUND_TOLERANCE: f32 = 0.1;
+/// Speed, in m/s, at which the player moving away from the ground counts as
+/// leaving it, as when jumping, rather than as still standing on it.
+pub const LIFTOFF_SPEED: f32 = 0.5;
+/// How far in front of the player's capsule to look for a ledge to step up
+/// onto.
+pub const STEP_PROBE_DISTANCE: f32 = 0.1;
+/// How far the sides of the hexes along the edge of the map extend downwards.
+pub const TERRAIN_SKIRT_DEPTH: f32 = 12.0;
+/// Simulated seconds per physics step.
+pub const TIMESTEP: f32 = 1.0 / 60.0;
+/// Most physics steps taken per call to `advance`. Any time left over beyond
+/// that is dropped, so that a slow frame makes the simulation lag behind
+/// instead of spiralling into ever longer frames.
+pub const MAX_SUBSTEPS: usize = 5;
+/// Default distance from the top of the lowest hex on the map down to the
+/// kill plane. Must exceed `TERRAIN_SKIRT_DEPTH`, so that the player is only
+/// respawned once they have fallen clear of the terrain.
+pub const DEFAULT_KILL_DEPTH: f32 = 20.0;
+/// How far above the top of its hex a hex trigger volume reaches.
+pub const HEX_TRIGGER_HEIGHT: f32 = 2.0;
+/// How far a raycast hit on the terrain is pushed back into the terrain to
+/// decide which hex it belongs to, so that hits on side walls count towards
+/// the hex that the wall belongs to.
+const HEX_PICK_DEPTH: f32 = 0.01;
+
+/// Tunable parameters of the player's character controller.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct CharacterSettings {
+    /// Top horizontal speed reachable by walking, in m/s.
+    pub max_speed:           f32,
+    /// Rate at which horizontal velocity approaches the desired velocity
+    /// while on the ground, in m/s^2.
+    pub ground_acceleration: f32,
+    /// Like `ground_acceleration`, but while airborne.
+    pub air_acceleration:    f32,
+    /// Rate at which horizontal velocity decays while on the ground with no
+    /// movement input, in m/s^2.
+    pub friction:            f32,
+    /// Tallest ledge that the player steps up onto without having to jump.
+    pub max_step_height:     f32,
+    /// Steepest ground, in radians from horizontal, that the player can still
+    /// stand on rather than slide down.
+    pub max_slope:           f32,
+    /// Upwards speed, in m/s, that the player gains by jumping.
+    pub jump_speed:          f32,
+}
+
+/// How each kind of hex surface behaves, both as a collider and when the
+/// player walks on it.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SurfaceProperties {
+    pub friction:     f32,
+    pub restitution:  f32,
+    /// Multiplies the player's ground acceleration and friction.
+    pub traction:     f32,
+    /// Multiplies the player's top walking speed.
+    pub speed_factor: f32,
+    /// Upwards speed, in m/s, that the player is launched at upon landing;
+    /// zero for surfaces that don't launch.
+    pub launch_speed: f32,
+}
+
+struct CharacterController {
+    settings:       CharacterSettings,
+    /// Horizontal direction that the player wants to move in; either of unit
+    /// length or zero.
+    move_dir:       na::Vector3<f32>,
+    jump_requested: bool,
+    /// Normal of the ground under the player as of the last step, if they are
+    /// standing on any.
+    ground_normal:  Option<na::Unit<na::Vector3<f32>>>,
+}
+
+struct StepClock {
+    last_time_stamp: Option<f64>,
+    accumulator:     f64,
+    /// Whether `advance` lets time pass without simulating any of it.
+    paused:          bool,
+}
+
+/// The player's center of mass after each of the last two physics steps, and
+/// how far real time has advanced between the two (in `[0, 1)`).
+struct PlayerInterpolation {
+    prev_com: na::Point3<f32>,
+    curr_com: na::Point3<f32>,
+    alpha:    f32,
+}
+
+/// Sent whenever the player falls below the kill plane and is put back onto
+/// a spawn point.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RespawnEvent {
+    /// The player's center of mass as of falling below the kill plane.
+    pub fell_from:   na::Point3<f32>,
+    pub spawn_point: SpawnPoint,
+}
+
+/// What a trigger volume was placed on.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TriggerAnchor {
+    /// Covers the hex, from its top up to `HEX_TRIGGER_HEIGHT` above it.
+    Hex(CubeCoord),
+    /// Placed freely, in world coordinates.
+    Position(na::Isometry3<f32>),
+}
+
+/// Something that happened between the player and another collider during a
+/// physics step.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CollisionEvent {
+    /// The player started overlapping the given trigger volume.
+    TriggerEnter(ColliderHandle),
+    /// The player stopped overlapping the given trigger volume.
+    TriggerExit(ColliderHandle),
+    /// The player started touching the given collider.
+    ContactStart(ColliderHandle),
+    /// The player stopped touching the given collider.
+    ContactStop(ColliderHandle),
+}
+
+/// The first collider that a ray hit.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RaycastHit {
+    pub collider: ColliderHandle,
+    pub point:    na::Point3<f32>,
+    pub normal:   na::Unit<na::Vector3<f32>>,
+    /// Distance from the origin of the ray to `point`.
+    pub distance: f32,
+    /// The hex that was hit, if the ray hit the terrain.
+    pub hex:      Option<CubeCoord>,
+}
+
+/// Everything about the state of the simulation that affects how it plays
+/// out from here on, so that it can be saved and later restored exactly.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct PhysicsSnapshot {
+    pub player:      BodySnapshot,
+    pub control:     ControlSnapshot,
+    /// In the same order as `Map::props`.
+    pub props:       Vec<BodySnapshot>,
+    /// Real time, in seconds, that has not been simulated yet.
+    pub accumulator: f64,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+pub struct BodySnapshot {
+    pub position:         [f32; 3],
+    /// Unit quaternion, as `[i, j, k, w]`.
+    pub orientation:      [f32; 4],
+    pub linear_velocity:  [f32; 3],
+    pub angular_velocity: [f32; 3],
+}
+
+/// The player's input, as last passed to the character controller.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+pub struct ControlSnapshot {
+    pub move_dir:       [f32; 3],
+    pub jump_requested: bool,
+    pub ground_normal:  Option<[f32; 3]>,
+}
+
+/// Height below which the player counts as having fallen off of the map,
+/// kept as an offset from the lowest hex so that it follows the map.
+struct KillPlane {
+    depth:         f32,
+    lowest_height: f32,
+}
+
+/// A trigger volume, along with what is needed to add it to the world again.
+struct Trigger {
+    anchor:   TriggerAnchor,
+    shape:    ShapeHandle<f32>,
+    position: na::Isometry3<f32>,
+}
+
+/// A prop's rigid body, along with its pose after each of the last two
+/// physics steps and the pose that it started out in.
+struct PropBody {
+    handle:       BodyHandle,
+    initial_pose: na::Isometry3<f32>,
+    prev_pose:    na::Isometry3<f32>,
+    curr_pose:    na::Isometry3<f32>,
+}
 
 lazy_static! {
     pub static ref WORLD: Mutex<World<f32>> =
         Mutex::new(World::new(time_in_sec));
     pub static ref PLAYER: Mutex<BodyHandle> =
         Mutex::new(BodyHandle::ground());
-    pub static ref CONTROL_ACC: Mutex<(na::Vector3<f32>, ForceGeneratorHandle)> =
-        Mutex::new((na::Vector3::zeros(), 0));
+    static ref CHARACTER: Mutex<CharacterController> =
+        Mutex::new(CharacterController {
+            settings:       CharacterSettings::default(),
+            move_dir:       na::Vector3::zeros(),
+            jump_requested: false,
+            ground_normal:  None,
+        });
+    static ref STEP_CLOCK: Mutex<StepClock> = Mutex::new(StepClock {
+        last_time_stamp: None,
+        accumulator:     0.0,
+        paused:          false,
+    });
+    static ref PLAYER_INTERP: Mutex<PlayerInterpolation> =
+        Mutex::new(PlayerInterpolation {
+            prev_com: na::Point3::origin(),
+            curr_com: na::Point3::origin(),
+            alpha:    0.0,
+        });
+    static ref KILL_PLANE: Mutex<KillPlane> = Mutex::new(KillPlane {
+        depth:         DEFAULT_KILL_DEPTH,
+        lowest_height: 0.0,
+    });
+    static ref RESPAWN_EVENTS: Mutex<Vec<RespawnEvent>> =
+        Mutex::new(Vec::new());
+    static ref PROPS: Mutex<Vec<PropBody>> = Mutex::new(Vec::new());
+    static ref PLAYER_COLLIDER: Mutex<Option<ColliderHandle>> =
+        Mutex::new(None);
+    static ref TRIGGERS: Mutex<HashMap<ColliderHandle, Trigger>> =
+        Mutex::new(HashMap::new());
+    static ref COLLISION_EVENTS: Mutex<Vec<CollisionEvent>> =
+        Mutex::new(Vec::new());
+    static ref TERRAIN_COLLIDERS: Mutex<Vec<ColliderHandle>> =
+        Mutex::new(Vec::new());
+    static ref CLOCK: Mutex<Box<Fn() -> f64 + Send>> =
+        Mutex::new(Box::new(default_clock));
 }
 
+/// Fills the physics world with the player, placed at `player_pos`, and with
+/// the terrain and props of `map_data`. Anything that was in the world
+/// before, including trigger volumes, is removed first.
 pub fn init_world(map_data: &map::Map, player_pos: &na::Point3<f32>) {
-    let mut world = WORLD.lock().unwrap();
+    build_world(&mut WORLD.lock().unwrap(), map_data, player_pos);
+    TRIGGERS.lock().unwrap().clear();
+
+    {
+        let mut character = CHARACTER.lock().unwrap();
+        character.move_dir = na::Vector3::zeros();
+        character.jump_requested = false;
+        character.ground_normal = None;
+    }
+
+    *STEP_CLOCK.lock().unwrap() = StepClock {
+        last_time_stamp: None,
+        accumulator:     0.0,
+        paused:          false,
+    };
+    *PLAYER_INTERP.lock().unwrap() = PlayerInterpolation {
+        prev_com: *player_pos,
+        curr_com: *player_pos,
+        alpha:    0.0,
+    };
+    KILL_PLANE.lock().unwrap().lowest_height =
+        map_data.lowest_height().unwrap_or(0.0);
+    RESPAWN_EVENTS.lock().unwrap().clear();
+    COLLISION_EVENTS.lock().unwrap().clear();
+}
 
+/// Replaces `world` with a new one holding only the player, the terrain, and
+/// the props, all at rest and in the same order every time, so that the
+/// same world is always built from the same arguments.
+fn build_world(
+    world: &mut World<f32>,
+    map_data: &map::Map,
+    player_pos: &na::Point3<f32>,
+) {
+    *world = World::new(time_in_sec);
     world.set_gravity(na::Vector3::y() * -STANDARD_GRAVITY);
+    world.set_timestep(TIMESTEP);
 
-    let cuboid = ShapeHandle::new(Cuboid::new(na::Vector3::repeat(1.0)));
-    let cuboid_inertia = cuboid.inertia(1.0);
-    let cuboid_center_of_mass = cuboid.center_of_mass();
+    let capsule = ShapeHandle::new(Capsule::new(
+        PLAYER_CAPSULE_HALF_HEIGHT,
+        PLAYER_RADIUS,
+    ));
+    let mut capsule_inertia = capsule.inertia(1.0);
+    // No angular inertia means that no torque can ever rotate the player, so
+    // they stay upright
+    capsule_inertia.angular = na::Matrix3::zeros();
+    let capsule_center_of_mass = capsule.center_of_mass();
     let player_body_handle = world.add_rigid_body(
         na::Isometry3::new(player_pos.coords, na::Vector3::zeros()),
-        cuboid_inertia,
-        cuboid_center_of_mass,
+        capsule_inertia,
+        capsule_center_of_mass,
     );
 
     *PLAYER.lock().unwrap() = player_body_handle;
 
-    let _player_collider = world.add_collider(
+    // Frictionless, since the character controller does its own friction
+    let player_collider = world.add_collider(
         COLLIDER_MARGIN,
-        ShapeHandle::new(Cuboid::new(na::Vector3::repeat(
-            1.0 - COLLIDER_MARGIN,
-        ))),
+        ShapeHandle::new(Capsule::new(
+            PLAYER_CAPSULE_HALF_HEIGHT,
+            PLAYER_RADIUS - COLLIDER_MARGIN,
+        )),
         player_body_handle,
         na::Isometry3::identity(),
-        Material::default(),
+        Material::new(0.0, 0.0),
     );
+    *PLAYER_COLLIDER.lock().unwrap() = Some(player_collider);
 
-    let hex_prism_verts = geometry::HEXAGONAL_PRISM_VERTS
-        .exact_chunks(3)
-        .map(|s| na::Point3::new(s[0], s[1], s[2]))
-        .collect::<Vec<_>>();
-    let hex_prism_indices = geometry::HEXAGONAL_PRISM_INDICES
-        .exact_chunks(3)
-        .map(|s| na::Point3::new(s[0], s[1], s[2]))
-        .collect::<Vec<_>>();
+    // One terrain collider per surface, so that each gets its own material
+    let mut terrain_colliders = TERRAIN_COLLIDERS.lock().unwrap();
+    terrain_colliders.clear();
+    for &surface in &Surface::ALL {
+        let hexes = map_data
+            .iter_coords()
+            .filter(|&cc| {
+                map_data
+                    .index_by_cube(cc)
+                    .map_or(false, |(hex, _)| hex.surface == surface)
+            }).collect::<Vec<_>>();
+        if hexes.is_empty() {
+            continue;
+        }
 
-    for (hex, (x, y)) in map_data.iter() {
-        world.add_collider(
+        let props = surface_properties(surface);
+        terrain_colliders.push(world.add_collider(
             COLLIDER_MARGIN,
-            ShapeHandle::new(TriMesh::new(
-                hex_prism_verts.clone(),
-                hex_prism_indices.clone(),
-                None,
-            )),
+            ShapeHandle::new(terrain_shape(map_data, hexes)),
             BodyHandle::ground(),
-            na::Isometry3::new(
-                na::Vector3::new(*x, hex.height, -(*y)),
-                na::Vector3::new(-FRAC_PI_2, 0.0, 0.0),
+            na::Isometry3::identity(),
+            Material::new(props.restitution, props.friction),
+        ));
+    }
+
+    let mut props = PROPS.lock().unwrap();
+    props.clear();
+    for prop in &map_data.props {
+        let shape = prop_shape(&prop.shape, 0.0);
+        let inertia = shape.inertia(prop.mass / shape.volume());
+        let pose = na::Isometry3::new(
+            na::Vector3::new(
+                prop.position[0],
+                prop.position[1],
+                prop.position[2],
             ),
+            na::Vector3::zeros(),
+        );
+        let handle =
+            world.add_rigid_body(pose, inertia, shape.center_of_mass());
+
+        world.add_collider(
+            COLLIDER_MARGIN,
+            prop_shape(&prop.shape, COLLIDER_MARGIN),
+            handle,
+            na::Isometry3::identity(),
             Material::default(),
         );
+        props.push(PropBody {
+            handle,
+            initial_pose: pose,
+            prev_pose: pose,
+            curr_pose: pose,
+        });
+    }
+}
+
+/// The collision shape of a prop, shrunk by `margin` on every side.
+fn prop_shape(shape: &PropShape, margin: f32) -> ShapeHandle<f32> {
+    match *shape {
+        PropShape::Cuboid(half_extents) =>
+            ShapeHandle::new(Cuboid::new(na::Vector3::new(
+                half_extents[0] - margin,
+                half_extents[1] - margin,
+                half_extents[2] - margin,
+            ))),
+        PropShape::Ball(radius) =>
+            ShapeHandle::new(Ball::new(radius - margin)),
+    }
+}
+
+/// Builds a single triangle mesh, in world coordinates, covering all of the
+/// given `hexes`: the top face of each, plus whichever parts of its sides are
+/// not hidden behind a neighboring hex. Sides along the edge of the map extend
+/// `TERRAIN_SKIRT_DEPTH` downwards.
+pub fn terrain_shape<M, I>(map_data: &M, hexes: I) -> TriMesh<f32>
+where
+    M: HexHeights + ?Sized,
+    I: IntoIterator<Item = CubeCoord>,
+{
+    let mut verts = Vec::new();
+    let mut indices = Vec::new();
+
+    for cc in hexes {
+        let height = match map_data.hex_height(cc) {
+            Some(h) => h,
+            None => continue,
+        };
+        let (x, y) = cc.to_pixel();
+        let corner = |k: usize, h: f32| {
+            let c = geometry::HEXAGON[k % 6];
+            na::Point3::new(x + c[0], h, -(y + c[1]))
+        };
+
+        // Top face
+        let center_ix = verts.len();
+        verts.push(na::Point3::new(x, height, -y));
+        for k in 0..6 {
+            verts.push(corner(k, height));
+        }
+        for k in 0..6 {
+            indices.push(na::Point3::new(
+                center_ix,
+                center_ix + 1 + k,
+                center_ix + 1 + (k + 1) % 6,
+            ));
+        }
+
+        // Sides
+        for (k, &dir) in geometry::HEXAGON_EDGE_DIRS.iter().enumerate() {
+            let bottom = match map_data.hex_height(cc.neighbor(dir)) {
+                Some(nh) if nh >= height => continue,
+                Some(nh) => nh,
+                None => height - TERRAIN_SKIRT_DEPTH,
+            };
+
+            let side_ix = verts.len();
+            verts.push(corner(k, height));
+            verts.push(corner(k, bottom));
+            verts.push(corner(k + 1, bottom));
+            verts.push(corner(k + 1, height));
+            indices.push(na::Point3::new(side_ix, side_ix + 1, side_ix + 2));
+            indices.push(na::Point3::new(side_ix, side_ix + 2, side_ix + 3));
+        }
+    }
+
+    TriMesh::new(verts, indices, None)
+}
+
+/// Where the player's center of mass goes when spawning at `spawn_point`, or
+/// `None` if the spawn point is not on the map.
+pub fn spawn_position(
+    map_data: &map::Map,
+    spawn_point: &SpawnPoint,
+) -> Option<na::Point3<f32>> {
+    map_data.index_by_cube(spawn_point.hex).map(|(hex, (x, y))| {
+        na::Point3::new(
+            *x,
+            hex.height + PLAYER_HALF_HEIGHT + SPAWN_CLEARANCE,
+            -(*y),
+        )
+    })
+}
+
+/// Runs as many fixed-length physics steps as fit into the time elapsed
+/// since the previous call (up to `MAX_SUBSTEPS`), carrying the remainder
+/// over to the next call. `time_stamp` is in milliseconds, as passed to
+/// `requestAnimationFrame` callbacks.
+pub fn advance(time_stamp: f64) {
+    let dt = f64::from(TIMESTEP);
+
+    let mut clock = STEP_CLOCK.lock().unwrap();
+    let elapsed = clock.last_time_stamp.map_or(0.0, |last| unsafe {
+        intrinsics::fdiv_fast((time_stamp - last).max(0.0), 1000.0)
+    });
+    clock.last_time_stamp = Some(time_stamp);
+    if clock.paused {
+        return;
     }
+    clock.accumulator += elapsed;
 
-    // Initial control accelerator
-    let mut ctrl_force =
-        ConstantAcceleration::new(na::Vector3::zeros(), na::Vector3::zeros());
+    let mut substeps = 0;
+    while clock.accumulator >= dt {
+        if substeps == MAX_SUBSTEPS {
+            clock.accumulator %= dt;
+            break;
+        }
 
-    ctrl_force.add_body_part(player_body_handle);
-    let ctrl_force_handle = world.add_force_generator(ctrl_force);
+        step();
+        clock.accumulator -= dt;
+        substeps += 1;
+    }
 
-    set_control_acc(na::Vector3::zeros(), ctrl_force_handle);
+    PLAYER_INTERP.lock().unwrap().alpha = (clock.accumulator / dt) as f32;
 }
 
 #[inline]
+pub fn is_paused() -> bool {
+    STEP_CLOCK.lock().unwrap().paused
+}
+
+/// Stops or restarts the simulation. While paused, `advance` doesn't
+/// simulate anything, but `step` still does.
+#[inline]
+pub fn set_paused(paused: bool) {
+    STEP_CLOCK.lock().unwrap().paused = paused;
+}
+
+/// Forgets when `advance` was last called, so that the next call doesn't
+/// simulate any of the time in between.
+#[inline]
+pub fn reset_clock() {
+    STEP_CLOCK.lock().unwrap().last_time_stamp = None;
+}
+
+/// Runs a single physics step of length `TIMESTEP`.
 pub fn step() {
-    WORLD.lock().unwrap().step();
+    let map_state = map::MAP.lock().unwrap();
+    let mut world = WORLD.lock().unwrap();
+    update_character(&mut world, &map_state);
+    world.step();
+    record_collision_events(&world);
+    respawn_if_fallen(&mut world, &map_state);
+    update_props(&mut world);
+
+    if let Some(player_body) = world.rigid_body(*PLAYER.lock().unwrap()) {
+        let mut interp = PLAYER_INTERP.lock().unwrap();
+        interp.prev_com = interp.curr_com;
+        interp.curr_com = player_body.center_of_mass();
+    }
+}
+
+/// The player's center of mass, interpolated between the last two physics
+/// steps according to how much real time has passed since the latest one.
+pub fn interpolated_player_com() -> na::Point3<f32> {
+    let interp = PLAYER_INTERP.lock().unwrap();
+
+    interp.prev_com + (interp.curr_com - interp.prev_com) * interp.alpha
+}
+
+/// Replaces the clock that the physics world reads the current time from,
+/// in seconds. The default clock is `performance.now()` on the web, and the
+/// system clock everywhere else.
+#[inline]
+pub fn set_clock<F: Fn() -> f64 + Send + 'static>(clock: F) {
+    *CLOCK.lock().unwrap() = Box::new(clock);
 }
 
 #[inline]
 fn time_in_sec() -> f64 {
+    (CLOCK.lock().unwrap())()
+}
+
+#[cfg(target_arch = "wasm32")]
+#[inline]
+fn default_clock() -> f64 {
     unsafe { intrinsics::fdiv_fast(js::now(), 1000.0) }
 }
 
-pub fn update_control_acc(lin_acc: na::Vector3<f32>) {
-    let (curr_lin_acc, curr_handle) = get_control_acc();
+#[cfg(not(target_arch = "wasm32"))]
+#[inline]
+fn default_clock() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as f64 + f64::from(d.subsec_nanos()) * 1.0e-9)
+        .unwrap_or(0.0)
+}
+
+/// The physical behavior of `surface`.
+pub fn surface_properties(surface: Surface) -> SurfaceProperties {
+    match surface {
+        Surface::Grass => SurfaceProperties {
+            friction:     0.8,
+            restitution:  0.0,
+            traction:     1.0,
+            speed_factor: 1.0,
+            launch_speed: 0.0,
+        },
+        Surface::Ice => SurfaceProperties {
+            friction:     0.05,
+            restitution:  0.0,
+            traction:     0.1,
+            speed_factor: 1.0,
+            launch_speed: 0.0,
+        },
+        Surface::Mud => SurfaceProperties {
+            friction:     1.0,
+            restitution:  0.0,
+            traction:     1.0,
+            speed_factor: 0.5,
+            launch_speed: 0.0,
+        },
+        Surface::BouncePad => SurfaceProperties {
+            friction:     0.8,
+            restitution:  0.6,
+            traction:     1.0,
+            speed_factor: 1.0,
+            launch_speed: 10.0,
+        },
+    }
+}
+
+impl CharacterSettings {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            max_speed:           6.0,
+            ground_acceleration: 40.0,
+            air_acceleration:    8.0,
+            friction:            30.0,
+            max_step_height:     0.6,
+            max_slope:           FRAC_PI_4,
+            jump_speed:          5.0,
+        }
+    }
+}
+
+impl Default for CharacterSettings {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[inline]
+pub fn character_settings() -> CharacterSettings {
+    CHARACTER.lock().unwrap().settings
+}
+
+#[inline]
+pub fn set_character_settings(settings: CharacterSettings) {
+    CHARACTER.lock().unwrap().settings = settings;
+}
+
+/// Sets the horizontal direction that the player is trying to move in, which
+/// should be either of unit length or zero.
+#[inline]
+pub fn set_move_direction(move_dir: na::Vector3<f32>) {
+    CHARACTER.lock().unwrap().move_dir = move_dir;
+}
+
+/// Makes the player jump on the next physics step, if they are standing on
+/// the ground by then. The ground is probed afresh on that step, rather than
+/// going by `is_grounded`.
+#[inline]
+pub fn jump() {
+    CHARACTER.lock().unwrap().jump_requested = true;
+}
+
+/// Whether the player was standing on the ground as of the last physics step.
+#[inline]
+pub fn is_grounded() -> bool {
+    CHARACTER.lock().unwrap().ground_normal.is_some()
+}
+
+/// The player's linear velocity as of the last physics step.
+#[inline]
+pub fn player_velocity() -> na::Vector3<f32> {
+    let world = WORLD.lock().unwrap();
+
+    world
+        .rigid_body(*PLAYER.lock().unwrap())
+        .map_or_else(na::Vector3::zeros, |b| b.velocity().linear)
+}
+
+/// Captures the current state of the simulation.
+pub fn snapshot() -> PhysicsSnapshot {
+    let world = WORLD.lock().unwrap();
+    let body_snapshot = |handle: BodyHandle| {
+        world
+            .rigid_body(handle)
+            .map(|b| BodySnapshot::new(b.position(), b.velocity()))
+            .unwrap_or_default()
+    };
+
+    let character = CHARACTER.lock().unwrap();
+    let control = ControlSnapshot {
+        move_dir:       vec3_to_array(&character.move_dir),
+        jump_requested: character.jump_requested,
+        ground_normal:  character.ground_normal.map(|n| vec3_to_array(&n)),
+    };
+
+    PhysicsSnapshot {
+        player: body_snapshot(*PLAYER.lock().unwrap()),
+        control,
+        props: PROPS
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|prop| body_snapshot(prop.handle))
+            .collect(),
+        accumulator: STEP_CLOCK.lock().unwrap().accumulator,
+    }
+}
+
+/// Puts the simulation back into the state captured by `snapshot`. The
+/// snapshot must have been taken on the same map.
+///
+/// The world is rebuilt from scratch, dropping whatever the solver kept
+/// between steps (such as contacts used for warm-starting), so the same
+/// snapshot always plays out the same way once restored. Play after the
+/// snapshot was taken may differ slightly, unless the snapshot was also
+/// restored right after it was taken. Trigger volumes are added back under
+/// new handles.
+pub fn restore(snapshot: &PhysicsSnapshot) -> Result<(), Error> {
+    let map_state = map::MAP.lock().unwrap();
+    if snapshot.props.len() != map_state.props.len() {
+        return Err(Error::Logic(format!(
+            "Snapshot has {} props, but the map has {}",
+            snapshot.props.len(),
+            map_state.props.len(),
+        )));
+    }
+
+    let mut world = WORLD.lock().unwrap();
+    let p = snapshot.player.position;
+    let player_pos = na::Point3::new(p[0], p[1], p[2]);
+    build_world(&mut world, &map_state, &player_pos);
+
+    // In a fixed order, rather than in whatever order the map holds them
+    let mut triggers = TRIGGERS
+        .lock()
+        .unwrap()
+        .drain()
+        .collect::<Vec<_>>();
+    triggers.sort_by_key(|&(handle, _)| handle);
+    for (_, trigger) in triggers {
+        add_sensor(&mut world, trigger);
+    }
+
+    let player_handle = *PLAYER.lock().unwrap();
+    if let Some(player_body) = world.rigid_body_mut(player_handle) {
+        snapshot.player.apply(player_body);
+
+        let com = player_body.center_of_mass();
+        let mut interp = PLAYER_INTERP.lock().unwrap();
+        interp.prev_com = com;
+        interp.curr_com = com;
+    }
+    let mut props = PROPS.lock().unwrap();
+    for (prop, prop_snapshot) in props.iter_mut().zip(&snapshot.props) {
+        if let Some(prop_body) = world.rigid_body_mut(prop.handle) {
+            prop_snapshot.apply(prop_body);
+
+            prop.prev_pose = *prop_body.position();
+            prop.curr_pose = prop.prev_pose;
+        }
+    }
+
+    {
+        let mut character = CHARACTER.lock().unwrap();
+        character.move_dir = array_to_vec3(snapshot.control.move_dir);
+        character.jump_requested = snapshot.control.jump_requested;
+        character.ground_normal = snapshot
+            .control
+            .ground_normal
+            .map(|n| na::Unit::new_normalize(array_to_vec3(n)));
+    }
+    STEP_CLOCK.lock().unwrap().accumulator = snapshot.accumulator;
+
+    Ok(())
+}
+
+impl PhysicsSnapshot {
+    #[inline]
+    pub fn from_raw_data(data: &[u8]) -> bincode::Result<Self> {
+        bincode::deserialize(data)
+    }
+
+    #[inline]
+    pub fn to_raw_data(&self) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(self)
+    }
+}
+
+impl BodySnapshot {
+    #[inline]
+    pub fn new(
+        position: &na::Isometry3<f32>,
+        velocity: &Velocity3<f32>,
+    ) -> Self {
+        let q = position.rotation.quaternion().coords;
+
+        Self {
+            position:         vec3_to_array(&position.translation.vector),
+            orientation:      [q[0], q[1], q[2], q[3]],
+            linear_velocity:  vec3_to_array(&velocity.linear),
+            angular_velocity: vec3_to_array(&velocity.angular),
+        }
+    }
+
+    #[inline]
+    pub fn pose(&self) -> na::Isometry3<f32> {
+        let q = self.orientation;
+        let rotation = na::Quaternion::new(q[3], q[0], q[1], q[2]);
+
+        na::Isometry3::from_parts(
+            na::Translation3::from_vector(array_to_vec3(self.position)),
+            na::Unit::new_normalize(rotation),
+        )
+    }
+
+    #[inline]
+    pub fn velocity(&self) -> Velocity3<f32> {
+        Velocity3::new(
+            array_to_vec3(self.linear_velocity),
+            array_to_vec3(self.angular_velocity),
+        )
+    }
+
+    fn apply(&self, body: &mut RigidBody<f32>) {
+        body.set_position(self.pose());
+        body.set_velocity(self.velocity());
+        body.activate();
+    }
+}
+
+impl Default for BodySnapshot {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            position:         [0.0; 3],
+            orientation:      [0.0, 0.0, 0.0, 1.0],
+            linear_velocity:  [0.0; 3],
+            angular_velocity: [0.0; 3],
+        }
+    }
+}
+
+#[inline]
+fn vec3_to_array(v: &na::Vector3<f32>) -> [f32; 3] {
+    [v[0], v[1], v[2]]
+}
+
+#[inline]
+fn array_to_vec3(a: [f32; 3]) -> na::Vector3<f32> {
+    na::Vector3::new(a[0], a[1], a[2])
+}
+
+/// Casts a ray from `origin` in the direction `dir`, returning the closest
+/// collider that it hits. The player and trigger volumes are ignored.
+pub fn raycast(
+    origin: &na::Point3<f32>,
+    dir: &na::Vector3<f32>,
+) -> Option<RaycastHit> {
+    let world = WORLD.lock().unwrap();
+    let player_collider = *PLAYER_COLLIDER.lock().unwrap();
+    let triggers = TRIGGERS.lock().unwrap();
+
+    let ray = Ray::new(*origin, dir.normalize());
+    let collision_groups = CollisionGroups::new();
+    let (collider, ray_hit) = world
+        .collision_world()
+        .interferences_with_ray(&ray, &collision_groups)
+        .map(|(co, hit)| (co.handle(), hit))
+        .filter(|(h, _)| {
+            Some(*h) != player_collider && !triggers.contains_key(h)
+        }).min_by(|(_, hit0), (_, hit1)| {
+            hit0.toi
+                .partial_cmp(&hit1.toi)
+                .unwrap_or(cmp::Ordering::Equal)
+        })?;
+
+    let point = ray.origin + ray.dir * ray_hit.toi;
+    let normal = na::Unit::new_normalize(ray_hit.normal);
+    let hex = if TERRAIN_COLLIDERS.lock().unwrap().contains(&collider) {
+        let inside = point - normal.unwrap() * HEX_PICK_DEPTH;
+
+        Some(geometry::pixel_to_cube(inside[0], -inside[2]))
+    } else {
+        None
+    };
+
+    Some(RaycastHit {
+        collider,
+        point,
+        normal,
+        distance: ray_hit.toi,
+        hex,
+    })
+}
+
+/// Adds a trigger volume covering the hex at `cc`, or returns `None` if `cc`
+/// is not on the map.
+pub fn add_hex_trigger(
+    map_data: &map::Map,
+    cc: CubeCoord,
+) -> Option<ColliderHandle> {
+    let &(ref hex, (x, y)) = map_data.index_by_cube(cc)?;
+    let half_height = HEX_TRIGGER_HEIGHT / 2.0;
+    // Cylinders in ncollide are upright, so it fits right on top of the hex
+    let shape = ShapeHandle::new(Cylinder::new(half_height, SQRT_3_ON_2));
+    let position = na::Isometry3::new(
+        na::Vector3::new(x, hex.height + half_height, -y),
+        na::Vector3::zeros(),
+    );
+
+    Some(add_sensor(
+        &mut WORLD.lock().unwrap(),
+        Trigger {
+            anchor: TriggerAnchor::Hex(cc),
+            shape,
+            position,
+        },
+    ))
+}
+
+/// Adds a trigger volume of the given `shape` at `position`, in world
+/// coordinates.
+#[inline]
+pub fn add_trigger(
+    shape: ShapeHandle<f32>,
+    position: na::Isometry3<f32>,
+) -> ColliderHandle {
+    add_sensor(
+        &mut WORLD.lock().unwrap(),
+        Trigger {
+            anchor: TriggerAnchor::Position(position),
+            shape,
+            position,
+        },
+    )
+}
+
+fn add_sensor(world: &mut World<f32>, trigger: Trigger) -> ColliderHandle {
+    let handle = world.add_sensor(
+        trigger.shape.clone(),
+        BodyHandle::ground(),
+        trigger.position,
+    );
+    TRIGGERS.lock().unwrap().insert(handle, trigger);
+
+    handle
+}
+
+/// Removes a trigger volume added by `add_hex_trigger` or `add_trigger`.
+pub fn remove_trigger(handle: ColliderHandle) {
+    if TRIGGERS.lock().unwrap().remove(&handle).is_some() {
+        WORLD.lock().unwrap().remove_colliders(&[handle]);
+    }
+}
+
+/// What the trigger volume `handle` was placed on, or `None` if it is not a
+/// trigger volume.
+#[inline]
+pub fn trigger_anchor(handle: ColliderHandle) -> Option<TriggerAnchor> {
+    TRIGGERS.lock().unwrap().get(&handle).map(|t| t.anchor)
+}
+
+/// Takes all of the collision events that have happened since the last
+/// call, in the order that they happened.
+#[inline]
+pub fn drain_collision_events() -> Vec<CollisionEvent> {
+    COLLISION_EVENTS.lock().unwrap().drain(..).collect()
+}
+
+/// Picks out the proximity and contact events of the last step that involve
+/// the player.
+fn record_collision_events(world: &World<f32>) {
+    let player_collider = match *PLAYER_COLLIDER.lock().unwrap() {
+        Some(h) => h,
+        None => return,
+    };
+    let other = |h1: ColliderHandle, h2: ColliderHandle| {
+        if h1 == player_collider {
+            Some(h2)
+        } else if h2 == player_collider {
+            Some(h1)
+        } else {
+            None
+        }
+    };
+
+    let mut events = COLLISION_EVENTS.lock().unwrap();
+    for e in world.proximity_events() {
+        let trigger = match other(e.collider1, e.collider2) {
+            Some(h) => h,
+            None => continue,
+        };
+
+        let was_inside = e.prev_status == Proximity::Intersecting;
+        let is_inside = e.new_status == Proximity::Intersecting;
+        if is_inside && !was_inside {
+            events.push(CollisionEvent::TriggerEnter(trigger));
+        } else if was_inside && !is_inside {
+            events.push(CollisionEvent::TriggerExit(trigger));
+        }
+    }
+    for e in world.contact_events() {
+        match *e {
+            ContactEvent::Started(h1, h2) =>
+                if let Some(h) = other(h1, h2) {
+                    events.push(CollisionEvent::ContactStart(h));
+                },
+            ContactEvent::Stopped(h1, h2) =>
+                if let Some(h) = other(h1, h2) {
+                    events.push(CollisionEvent::ContactStop(h));
+                },
+        }
+    }
+}
+
+/// The pose of each of the map's props, in the same order as `Map::props`,
+/// interpolated in the same way as `interpolated_player_com`.
+pub fn interpolated_prop_poses() -> Vec<na::Isometry3<f32>> {
+    let alpha = PLAYER_INTERP.lock().unwrap().alpha;
 
-    //js::log(&format!("{:?}, {:?}", curr_lin_acc, lin_acc));
+    PROPS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|prop| {
+            let (prev, curr) = (&prop.prev_pose, &prop.curr_pose);
+            let translation = prev.translation.vector
+                + (curr.translation.vector - prev.translation.vector) * alpha;
+            let rotation = prev
+                .rotation
+                .try_slerp(&curr.rotation, alpha, 1.0e-6)
+                .unwrap_or(curr.rotation);
 
-    if curr_lin_acc != lin_acc {
-        let mut world = WORLD.lock().unwrap();
-        world.remove_force_generator(curr_handle);
+            na::Isometry3::from_parts(
+                na::Translation3::from_vector(translation),
+                rotation,
+            )
+        }).collect()
+}
 
-        let mut ctrl_force =
-            ConstantAcceleration::new(lin_acc, na::Vector3::zeros());
-        ctrl_force.add_body_part(*PLAYER.lock().unwrap());
-        let ctrl_force_handle = world.add_force_generator(ctrl_force);
+/// Records the pose of every prop for interpolation, first putting any prop
+/// that has fallen below the kill plane back where it started, at rest.
+fn update_props(world: &mut World<f32>) {
+    let kill_height = kill_height();
 
-        //js::log(&format!("ctrl_force_handle: {}", ctrl_force_handle));
+    for prop in PROPS.lock().unwrap().iter_mut() {
+        let prop_body = match world.rigid_body_mut(prop.handle) {
+            Some(b) => b,
+            None => continue,
+        };
 
-        set_control_acc(lin_acc, ctrl_force_handle);
+        prop.prev_pose = prop.curr_pose;
+        if prop_body.center_of_mass()[1] < kill_height {
+            prop_body.set_position(prop.initial_pose);
+            prop_body.set_velocity(Velocity3::zero());
+            prop.prev_pose = prop.initial_pose;
+        }
+        prop.curr_pose = *prop_body.position();
     }
 }
 
+/// Height of the kill plane, below which the player is respawned.
+#[inline]
+pub fn kill_height() -> f32 {
+    let kill_plane = KILL_PLANE.lock().unwrap();
+
+    kill_plane.lowest_height - kill_plane.depth
+}
+
+/// Sets how far below the lowest hex on the map the kill plane sits.
 #[inline]
-fn set_control_acc(lin_acc: na::Vector3<f32>, handle: ForceGeneratorHandle) {
-    *CONTROL_ACC.lock().unwrap() = (lin_acc, handle);
+pub fn set_kill_depth(depth: f32) {
+    KILL_PLANE.lock().unwrap().depth = depth;
 }
 
+/// Takes all of the respawn events that have happened since the last call.
 #[inline]
-fn get_control_acc() -> (na::Vector3<f32>, ForceGeneratorHandle) {
-    *CONTROL_ACC.lock().unwrap()
+pub fn drain_respawn_events() -> Vec<RespawnEvent> {
+    RESPAWN_EVENTS.lock().unwrap().drain(..).collect()
+}
+
+/// If the player has fallen below the kill plane, puts them back onto the
+/// spawn point nearest to where they fell, at rest, and records a
+/// `RespawnEvent`.
+fn respawn_if_fallen(world: &mut World<f32>, map_data: &map::Map) {
+    let player_handle = *PLAYER.lock().unwrap();
+    let player_body = match world.rigid_body_mut(player_handle) {
+        Some(b) => b,
+        None => return,
+    };
+
+    let com = player_body.center_of_mass();
+    if com[1] >= kill_height() {
+        return;
+    }
+
+    let spawn_point = map_data
+        .nearest_spawn_point(geometry::pixel_to_cube(com[0], -com[2]));
+    let spawn_pos = match spawn_position(map_data, &spawn_point) {
+        Some(p) => p,
+        None => return,
+    };
+
+    // The body's origin is its center of mass, since the capsule is
+    // symmetric
+    player_body.set_position(na::Isometry3::new(
+        spawn_pos.coords,
+        na::Vector3::zeros(),
+    ));
+    player_body.set_velocity(Velocity3::zero());
+    player_body.activate();
+
+    {
+        let mut character = CHARACTER.lock().unwrap();
+        character.jump_requested = false;
+        character.ground_normal = None;
+    }
+    {
+        // Don't interpolate across the teleport
+        let mut interp = PLAYER_INTERP.lock().unwrap();
+        interp.prev_com = spawn_pos;
+        interp.curr_com = spawn_pos;
+    }
+
+    RESPAWN_EVENTS.lock().unwrap().push(RespawnEvent {
+        fell_from: com,
+        spawn_point,
+    });
+}
+
+/// Drives the player's body according to their movement input: accelerates
+/// them towards the desired horizontal velocity, keeps them on the ground
+/// when walking over it, steps them up onto low ledges, and makes them jump.
+fn update_character(world: &mut World<f32>, map_data: &map::Map) {
+    let mut character = CHARACTER.lock().unwrap();
+    let settings = character.settings;
+    let player_handle = *PLAYER.lock().unwrap();
+
+    let (com, lin_vel) = match world.rigid_body(player_handle) {
+        Some(b) => (b.center_of_mass(), b.velocity().linear),
+        None => return,
+    };
+    // Ground that the player is already moving away from doesn't count,
+    // otherwise following it would cancel a jump as soon as it starts
+    character.ground_normal = probe_ground(world, player_handle, &com)
+        .filter(|n| n[1] >= settings.max_slope.cos())
+        .filter(|n| n.dot(&lin_vel) <= LIFTOFF_SPEED);
+    let ground_normal = character.ground_normal;
+    let move_dir = character.move_dir;
+    let jump = character.jump_requested && ground_normal.is_some();
+    character.jump_requested = false;
+
+    let surface = ground_normal.and_then(|_| {
+        map_data
+            .index_by_cube(geometry::pixel_to_cube(com[0], -com[2]))
+            .map(|(hex, _)| surface_properties(hex.surface))
+    });
+
+    let step_up = if ground_normal.is_some() && move_dir != na::zero() {
+        step_up_height(map_data, &com, &move_dir, &settings)
+    } else {
+        None
+    };
+
+    let player_body = match world.rigid_body_mut(player_handle) {
+        Some(b) => b,
+        None => return,
+    };
+    let mut velocity = *player_body.velocity();
+
+    let horizontal = na::Vector3::new(lin_vel[0], 0.0, lin_vel[2]);
+    let (traction, speed_factor) =
+        surface.map_or((1.0, 1.0), |s| (s.traction, s.speed_factor));
+    let acceleration = match ground_normal {
+        Some(_) if move_dir == na::zero() => settings.friction * traction,
+        Some(_) => settings.ground_acceleration * traction,
+        None => settings.air_acceleration,
+    };
+    let mut new_lin_vel = move_towards(
+        &horizontal,
+        &(move_dir * settings.max_speed * speed_factor),
+        acceleration * TIMESTEP,
+    ) + na::Vector3::y() * lin_vel[1];
+
+    if let Some(n) = ground_normal {
+        // Follow the ground rather than bouncing off of or sinking into it
+        new_lin_vel -= n.unwrap() * n.dot(&new_lin_vel);
+    }
+    if jump {
+        new_lin_vel[1] = settings.jump_speed;
+    }
+    if let Some(s) = surface.filter(|s| s.launch_speed > 0.0) {
+        new_lin_vel[1] = new_lin_vel[1].max(s.launch_speed);
+    }
+
+    if let Some(rise) = step_up {
+        let mut position = *player_body.position();
+        position.translation.vector[1] += rise + COLLIDER_MARGIN;
+        player_body.set_position(position);
+
+        new_lin_vel[1] = new_lin_vel[1].max(0.0);
+    }
+
+    velocity.linear = new_lin_vel;
+    player_body.set_velocity(velocity);
+    player_body.activate();
+}
+
+/// Casts a ray straight down from `com`, returning the normal of the ground
+/// that it hits if that ground is within `GROUND_TOLERANCE` of the bottom of
+/// the player. The player and trigger volumes are not ground.
+fn probe_ground(
+    world: &World<f32>,
+    player_handle: BodyHandle,
+    com: &na::Point3<f32>,
+) -> Option<na::Unit<na::Vector3<f32>>> {
+    let triggers = TRIGGERS.lock().unwrap();
+    let ray = Ray::new(*com, -na::Vector3::y());
+    let collision_groups = CollisionGroups::new();
+    let max_toi = PLAYER_HALF_HEIGHT + GROUND_TOLERANCE;
+
+    world
+        .collision_world()
+        .interferences_with_ray(&ray, &collision_groups)
+        .filter(|(co, hit)| {
+            co.data().body() != player_handle
+                && !triggers.contains_key(&co.handle())
+                && hit.toi <= max_toi
+        }).min_by(|(_, hit0), (_, hit1)| {
+            hit0.toi
+                .partial_cmp(&hit1.toi)
+                .unwrap_or(cmp::Ordering::Equal)
+        })
+        // A ray that starts inside of a shape may not get a usable normal
+        .and_then(|(_, hit)| na::Unit::try_new(hit.normal, 1.0e-6))
+}
+
+/// How far the player would have to be raised to step onto the hex directly
+/// ahead of them, if it is a ledge that is low enough to step onto.
+fn step_up_height(
+    map_data: &map::Map,
+    com: &na::Point3<f32>,
+    move_dir: &na::Vector3<f32>,
+    settings: &CharacterSettings,
+) -> Option<f32> {
+    let probe = com + move_dir * (PLAYER_RADIUS + STEP_PROBE_DISTANCE);
+    let (hex, _) = map_data
+        .index_by_cube(geometry::pixel_to_cube(probe[0], -probe[2]))?;
+
+    let rise = hex.height - (com[1] - PLAYER_HALF_HEIGHT);
+    if rise > COLLIDER_MARGIN && rise <= settings.max_step_height {
+        Some(rise)
+    } else {
+        None
+    }
+}
+
+/// Moves `from` towards `to` by at most `max_delta`.
+#[inline]
+fn move_towards(
+    from: &na::Vector3<f32>,
+    to: &na::Vector3<f32>,
+    max_delta: f32,
+) -> na::Vector3<f32> {
+    let diff = to - from;
+    let dist = diff.norm();
+
+    if dist <= max_delta {
+        *to
+    } else {
+        from + diff * (max_delta / dist)
+    }
 }
@@ -0,0 +1,271 @@
+use bincode;
+use camera::{self, ModeSnapshot};
+use controls::{self, KeyBindings, StickSettings};
+use error::Error;
+use mains;
+use na;
+use physics::{self, PhysicsSnapshot};
+use settings::{self, Settings};
+use std::{mem, sync::Mutex};
+
+/// Bumped whenever the format of `Recording` changes, so that old recordings
+/// are rejected instead of being played back wrongly.
+const RECORDING_VERSION: u32 = 3;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct RecordedEvent {
+    pub opcode:  u8,
+    pub payload: Vec<u8>,
+}
+
+/// The events handled during a single call to `main_loop`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct RecordedFrame {
+    pub time_stamp: f64,
+    pub events:     Vec<RecordedEvent>,
+}
+
+/// Everything needed to play a session back exactly as it happened: which
+/// map it was on, the state that it started in, the player's settings, and
+/// every event that was handled. Whether the game was paused and the
+/// camera's mode are part of the starting state, since events toggle them
+/// rather than setting them.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Recording {
+    version:            u32,
+    pub map_seed:       u64,
+    pub orient:         [f32; 3],
+    pub start:          PhysicsSnapshot,
+    pub paused:         bool,
+    pub camera:         ModeSnapshot,
+    pub key_bindings:   KeyBindings,
+    pub settings:       Settings,
+    pub stick_settings: StickSettings,
+    pub frames:         Vec<RecordedFrame>,
+}
+
+enum Mode {
+    Live,
+    Recording(Recording),
+    /// Waiting for the recording's map to be loaded.
+    ReplayPending(Recording),
+    Replaying {
+        recording:  Recording,
+        next_frame: usize,
+        /// The player's own settings, pause state, and camera mode, to put
+        /// back once the replay is over.
+        saved:      (KeyBindings, Settings, StickSettings, bool, ModeSnapshot),
+    },
+}
+
+lazy_static! {
+    static ref MODE: Mutex<Mode> = Mutex::new(Mode::Live);
+}
+
+/// Starts recording from the current state of the game, which must be on
+/// the map generated from `map_seed`. Any keys or buttons that are held down
+/// are released first, so that the recording doesn't depend on them.
+pub fn start_recording(map_seed: u64) -> Result<(), Error> {
+    let mut mode = MODE.lock().unwrap();
+    match *mode {
+        Mode::Live | Mode::Recording(_) => (),
+        _ => {
+            return Err(Error::Logic(
+                "Cannot record while replaying".to_owned(),
+            ))
+        },
+    }
+
+    controls::reset();
+    physics::reset_clock();
+    mains::reset_frame_clock();
+    // Restoring rebuilds the world, so start from the rebuilt one, just as
+    // the replay will
+    let start = physics::snapshot();
+    physics::restore(&start)?;
+
+    let orient = mains::PLAYER_STATE.lock().unwrap().orient;
+    *mode = Mode::Recording(Recording {
+        version: RECORDING_VERSION,
+        map_seed,
+        orient: [orient[0], orient[1], orient[2]],
+        start,
+        paused: physics::is_paused(),
+        camera: camera::mode_snapshot(),
+        key_bindings: controls::key_bindings(),
+        settings: settings::settings(),
+        stick_settings: controls::stick_settings(),
+        frames: Vec::new(),
+    });
+
+    Ok(())
+}
+
+/// Stops recording, returning what was recorded, if anything.
+pub fn stop_recording() -> Option<Recording> {
+    let mut mode = MODE.lock().unwrap();
+    match mem::replace(&mut *mode, Mode::Live) {
+        Mode::Recording(recording) => Some(recording),
+        other => {
+            *mode = other;
+
+            None
+        },
+    }
+}
+
+/// Adds a frame to the recording, if there is one.
+#[inline]
+pub fn record_frame(time_stamp: f64, events: &[RecordedEvent]) {
+    if let Mode::Recording(ref mut recording) = *MODE.lock().unwrap() {
+        recording.frames.push(RecordedFrame {
+            time_stamp,
+            events: events.to_vec(),
+        });
+    }
+}
+
+/// Queues up `recording` to be played back as soon as its map is loaded,
+/// stopping any recording or replay that was going on.
+pub fn queue_replay(recording: Recording) {
+    finish_replay();
+    *MODE.lock().unwrap() = Mode::ReplayPending(recording);
+}
+
+/// Seed of the map that the queued replay needs, if there is one.
+#[inline]
+pub fn pending_map_seed() -> Option<u64> {
+    match *MODE.lock().unwrap() {
+        Mode::ReplayPending(ref recording) => Some(recording.map_seed),
+        _ => None,
+    }
+}
+
+/// Starts playing back the queued replay, if there is one, now that the map
+/// generated from `map_seed` has been loaded.
+pub fn start_pending_replay(map_seed: u64) -> Result<(), Error> {
+    let mut mode = MODE.lock().unwrap();
+    let recording = match mem::replace(&mut *mode, Mode::Live) {
+        Mode::ReplayPending(recording) => recording,
+        other => {
+            *mode = other;
+
+            return Ok(());
+        },
+    };
+    if recording.map_seed != map_seed {
+        return Err(Error::Logic(format!(
+            "Recording was made on the map with seed {}, but the map with \
+             seed {} was loaded",
+            recording.map_seed, map_seed,
+        )));
+    }
+    recording.settings.validate()?;
+
+    physics::restore(&recording.start)?;
+    physics::reset_clock();
+    mains::reset_frame_clock();
+    controls::reset();
+    mains::PLAYER_STATE.lock().unwrap().orient =
+        na::Unit::new_normalize(na::Vector3::new(
+            recording.orient[0],
+            recording.orient[1],
+            recording.orient[2],
+        ));
+
+    let saved = (
+        controls::key_bindings(),
+        settings::settings(),
+        controls::stick_settings(),
+        physics::is_paused(),
+        camera::mode_snapshot(),
+    );
+    controls::set_key_bindings(recording.key_bindings.clone());
+    // Already validated above
+    let _ = settings::set_settings(recording.settings);
+    controls::set_stick_settings(recording.stick_settings);
+    physics::set_paused(recording.paused);
+    camera::restore_mode(&recording.camera);
+
+    *mode = Mode::Replaying {
+        recording,
+        next_frame: 0,
+        saved,
+    };
+
+    Ok(())
+}
+
+/// The next frame of the replay, or `None` if there is no replay going on.
+/// Once the last frame has been played, the player's own settings are put
+/// back and the game carries on live.
+pub fn next_replay_frame() -> Option<RecordedFrame> {
+    {
+        let mut mode = MODE.lock().unwrap();
+        if let Mode::Replaying {
+            ref recording,
+            ref mut next_frame,
+            ..
+        } = *mode
+        {
+            if let Some(frame) = recording.frames.get(*next_frame) {
+                *next_frame += 1;
+
+                return Some(frame.clone());
+            }
+        } else {
+            return None;
+        }
+    }
+
+    finish_replay();
+
+    None
+}
+
+#[inline]
+pub fn is_replaying() -> bool {
+    match *MODE.lock().unwrap() {
+        Mode::Replaying { .. } => true,
+        _ => false,
+    }
+}
+
+/// Stops any recording or replay, putting back the player's own settings,
+/// pause state, and camera mode if a replay was stopped.
+fn finish_replay() {
+    let mode = mem::replace(&mut *MODE.lock().unwrap(), Mode::Live);
+    if let Mode::Replaying { saved, .. } = mode {
+        let (key_bindings, settings, stick_settings, paused, camera_mode) =
+            saved;
+        controls::set_key_bindings(key_bindings);
+        // These were valid when they were saved, so they still are
+        let _ = settings::set_settings(settings);
+        controls::set_stick_settings(stick_settings);
+        physics::set_paused(paused);
+        camera::restore_mode(&camera_mode);
+
+        controls::reset();
+        physics::reset_clock();
+        mains::reset_frame_clock();
+    }
+}
+
+impl Recording {
+    pub fn from_raw_data(data: &[u8]) -> Result<Self, Error> {
+        let recording: Self = bincode::deserialize(data)?;
+        if recording.version != RECORDING_VERSION {
+            return Err(Error::Logic(format!(
+                "Recording has version {}, but only version {} is supported",
+                recording.version, RECORDING_VERSION,
+            )));
+        }
+
+        Ok(recording)
+    }
+
+    #[inline]
+    pub fn to_raw_data(&self) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(self)
+    }
+}
@@ -1,20 +1,31 @@
+use camera::{self, CameraMode};
 use error::Error;
 use geometry;
-use mains;
 use map;
 use na;
 use physics;
+use settings;
 use std::{f32::consts::FRAC_PI_2, sync::Mutex};
 use webgl;
+use webgl_test_common::PropShape;
 
 struct GlState {
     terrain: TerrainRendering,
+    props:   PropRendering,
+    player:  Mesh,
     skybox:  SkyboxRendering,
 }
 
+/// Vertex positions and normals uploaded to the GPU, laid out for the
+/// terrain program.
+struct Mesh {
+    vao:          webgl::WebGLVertexArrayObject,
+    vertex_count: i32,
+}
+
 struct TerrainRendering {
     program:                   webgl::WebGLProgram,
-    vao:                       webgl::WebGLVertexArrayObject,
+    prism:                     Mesh,
     world_uni_loc:             webgl::WebGLUniformLocation,
     world_view_proj_uni_loc:   webgl::WebGLUniformLocation,
     reverse_light_dir_uni_loc: webgl::WebGLUniformLocation,
@@ -22,6 +33,13 @@ struct TerrainRendering {
     displacement_uni_loc:      webgl::WebGLUniformLocation,
 }
 
+/// Unit-sized meshes for each prop shape, drawn with the terrain program and
+/// scaled to size.
+struct PropRendering {
+    box_mesh:    Mesh,
+    sphere_mesh: Mesh,
+}
+
 struct SkyboxRendering {
     program:         webgl::WebGLProgram,
     vao:             webgl::WebGLVertexArrayObject,
@@ -67,7 +85,7 @@ uniform vec3 u_color;
 out vec4 outColor;
 
 void main() {
-    float light = dot(v_normal, u_reverseLightDirection);
+    float light = dot(normalize(v_normal), u_reverseLightDirection);
 
     outColor = vec4(u_color * light, 1.0);
 }
@@ -106,6 +124,78 @@ void main() {
 
 const SKYBOX_TEXTURE_INDEX: webgl::TextureIndex =
     webgl::TextureIndex::Texture0;
+const SPHERE_RINGS: usize = 12;
+const SPHERE_SEGMENTS: usize = 24;
+const PLAYER_COLOR: [f32; 3] = [0.85, 0.35, 0.2];
+
+impl Mesh {
+    fn new(
+        position_attr_loc: u32,
+        normal_attr_loc: u32,
+        verts: &[f32],
+        normals: &[f32],
+    ) -> Self {
+        let vert_buffer = webgl::create_buffer();
+        webgl::bind_buffer(webgl::BufferType::ArrayBuffer, &vert_buffer);
+        webgl::buffer_data_f32(
+            webgl::BufferType::ArrayBuffer,
+            verts,
+            webgl::UsageType::StaticDraw,
+            0,
+            None,
+        );
+
+        let normal_buffer = webgl::create_buffer();
+        webgl::bind_buffer(webgl::BufferType::ArrayBuffer, &normal_buffer);
+        webgl::buffer_data_f32(
+            webgl::BufferType::ArrayBuffer,
+            normals,
+            webgl::UsageType::StaticDraw,
+            0,
+            None,
+        );
+        ////////////////////////////////////////////////////////////////////
+
+        let vao = webgl::create_vertex_array();
+        webgl::bind_vertex_array(&vao);
+        webgl::bind_buffer(webgl::BufferType::ArrayBuffer, &vert_buffer);
+        webgl::enable_vertex_attr_array(position_attr_loc);
+        webgl::vertex_attr_ptr(
+            position_attr_loc,
+            3,                      // Three components per iteration
+            webgl::DataType::Float, // The data is `f32`s
+            false,                  // Don't normalize to clip space
+            0,                      // Stride (in bytes)
+            0,                      // Offset (in bytes)
+        );
+
+        webgl::bind_buffer(webgl::BufferType::ArrayBuffer, &normal_buffer);
+        webgl::enable_vertex_attr_array(normal_attr_loc);
+        webgl::vertex_attr_ptr(
+            normal_attr_loc,
+            3,                      // Three components per iteration
+            webgl::DataType::Float, // The data is `f32`s
+            false,                  // Do not normalize
+            0,                      // Stride (in bytes)
+            0,                      // Offset (in bytes)
+        );
+
+        Self {
+            vao,
+            vertex_count: verts.len() as i32 / 3,
+        }
+    }
+
+    #[inline]
+    fn draw(&self) {
+        webgl::bind_vertex_array(&self.vao);
+        webgl::draw_arrays(
+            webgl::RenderingPrimitive::Triangles,
+            0,
+            self.vertex_count,
+        );
+    }
+}
 
 pub fn init() -> Result<(), Error> {
     let vertex_shader = webgl::create_shader(
@@ -176,55 +266,52 @@ pub fn init() -> Result<(), Error> {
     let normal_attr_loc = normal_attr_loc as u32;
     ////////////////////////////////////////////////////////////////////////
 
-    let prism_vert_buffer = webgl::create_buffer();
-    webgl::bind_buffer(webgl::BufferType::ArrayBuffer, &prism_vert_buffer);
-    webgl::buffer_data_f32(
-        webgl::BufferType::ArrayBuffer,
+    let prism = Mesh::new(
+        position_attr_loc,
+        normal_attr_loc,
         geometry::HEXAGONAL_PRISM,
-        webgl::UsageType::StaticDraw,
-        0,
-        None,
-    );
-
-    let prism_normal_buffer = webgl::create_buffer();
-    webgl::bind_buffer(webgl::BufferType::ArrayBuffer, &prism_normal_buffer);
-    webgl::buffer_data_f32(
-        webgl::BufferType::ArrayBuffer,
         geometry::HEXAGONAL_PRISM_NORMALS,
-        webgl::UsageType::StaticDraw,
-        0,
-        None,
-    );
-    ////////////////////////////////////////////////////////////////////////
-
-    let vao = webgl::create_vertex_array();
-    webgl::bind_vertex_array(&vao);
-    webgl::bind_buffer(webgl::BufferType::ArrayBuffer, &prism_vert_buffer);
-    webgl::enable_vertex_attr_array(position_attr_loc);
-    webgl::vertex_attr_ptr(
-        position_attr_loc,
-        3,                      // Three components per iteration
-        webgl::DataType::Float, // The data is `f32`s
-        false,                  // Don't normalize to clip space
-        0,                      // Stride (in bytes)
-        0,                      // Offset (in bytes)
     );
+    let box_verts = geometry::box_mesh();
+    let props = PropRendering {
+        box_mesh:    Mesh::new(
+            position_attr_loc,
+            normal_attr_loc,
+            &box_verts,
+            &geometry::flat_normals(&box_verts),
+        ),
+        sphere_mesh: {
+            let sphere_verts =
+                geometry::sphere_mesh(SPHERE_RINGS, SPHERE_SEGMENTS);
+
+            Mesh::new(
+                position_attr_loc,
+                normal_attr_loc,
+                &sphere_verts,
+                &sphere_verts,
+            )
+        },
+    };
+    let player = {
+        let (capsule_verts, capsule_normals) = geometry::capsule_mesh(
+            physics::PLAYER_CAPSULE_HALF_HEIGHT,
+            physics::PLAYER_RADIUS,
+            SPHERE_RINGS,
+            SPHERE_SEGMENTS,
+        );
 
-    webgl::bind_buffer(webgl::BufferType::ArrayBuffer, &prism_normal_buffer);
-    webgl::enable_vertex_attr_array(normal_attr_loc);
-    webgl::vertex_attr_ptr(
-        normal_attr_loc,
-        3,                      // Three components per iteration
-        webgl::DataType::Float, // The data is `f32`s
-        false,                  // Do not normalize
-        0,                      // Stride (in bytes)
-        0,                      // Offset (in bytes)
-    );
+        Mesh::new(
+            position_attr_loc,
+            normal_attr_loc,
+            &capsule_verts,
+            &capsule_normals,
+        )
+    };
     ////////////////////////////////////////////////////////////////////////
 
     let terrain = TerrainRendering {
         program,
-        vao,
+        prism,
         world_uni_loc,
         world_view_proj_uni_loc,
         reverse_light_dir_uni_loc,
@@ -339,7 +426,12 @@ pub fn init() -> Result<(), Error> {
         textures_loaded: false,
     };
 
-    *GL_STATE.lock().unwrap() = Some(GlState { terrain, skybox });
+    *GL_STATE.lock().unwrap() = Some(GlState {
+        terrain,
+        props,
+        player,
+        skybox,
+    });
     ////////////////////////////////////////////////////////////////////////
 
     // Reset the canvas
@@ -363,14 +455,6 @@ pub fn render() -> Result<(), Error> {
         Error::Logic("Did not call `init` before callng `render`".to_owned())
     })?;
 
-    // Retrieve physical state
-    let world = physics::WORLD.lock().unwrap();
-    let player_body =
-        world.rigid_body(*physics::PLAYER.lock().unwrap()).unwrap();
-
-    // Retrieve player-specific state
-    let player_state = mains::PLAYER_STATE.lock().unwrap();
-
     // Retrieve map state
     let map_state = map::MAP.lock().unwrap();
 
@@ -383,21 +467,18 @@ pub fn render() -> Result<(), Error> {
     // Compute transformation matrices
     let proj = na::Matrix4::new_perspective(
         webgl::get_canvas_width() / webgl::get_canvas_height(),
-        0.875,
+        settings::settings().fov,
         1.0,
         2000.0,
     );
     let world =
         na::Matrix4::new_rotation(na::Vector3::new(-FRAC_PI_2, 0.0, 0.0));
-    let player_com = player_body.center_of_mass();
-    let player_orient = player_state.orient.unwrap();
-    let view = na::Matrix4::look_at_rh(
-        &player_com,
-        &(player_com + player_orient),
-        &na::Vector3::y(),
-    ) * world;
+    let player_com = physics::interpolated_player_com();
+    let camera_view = camera::view();
+    let camera = camera_view.look_at();
+    let view = camera * world;
     let view_rot_only =
-        na::Rotation3::look_at_rh(&player_orient, &na::Vector3::y());
+        na::Rotation3::look_at_rh(&camera_view.orient, &na::Vector3::y());
     let view_proj = proj * view;
 
     ////////////////////////////////////////////////////////////////////
@@ -407,7 +488,7 @@ pub fn render() -> Result<(), Error> {
     // Tell it to use the terrain program (pair of shaders)
     webgl::use_program(&gl_state.terrain.program);
     // Bind the attribute/buffer set we want
-    webgl::bind_vertex_array(&gl_state.terrain.vao);
+    webgl::bind_vertex_array(&gl_state.terrain.prism.vao);
     ////////////////////////////////////////////////////////////////////
 
     // Pass in the world matrix
@@ -432,14 +513,16 @@ pub fn render() -> Result<(), Error> {
     );
 
     let player_cube_coord =
-        geometry::pixel_to_cube(player_com[0], player_com[2]);
+        geometry::pixel_to_cube(player_com[0], -player_com[2]);
     let player_hex = map_state.index_by_cube(player_cube_coord);
     for (hex, (x, y)) in player_hex
         .into_iter()
         .chain(map_state.iter_radial(player_cube_coord))
     {
         // Set the color
-        let color = map::RgbColor::from_byte_color(hex.color);
+        let (tint, tint_strength) = map::RgbColor::surface_tint(hex.surface);
+        let color = map::RgbColor::from_byte_color(hex.color)
+            .mix(&tint, tint_strength);
         webgl::uniform3f(
             &gl_state.terrain.color_uni_loc,
             color.r(),
@@ -460,11 +543,87 @@ pub fn render() -> Result<(), Error> {
         webgl::draw_arrays(
             webgl::RenderingPrimitive::Triangles,
             0,
-            geometry::HEXAGONAL_PRISM.len() as i32 / 3,
+            gl_state.terrain.prism.vertex_count,
         );
         ////////////////////////////////////////////////////////////////////
     }
 
+    ////////////////////////////////////////////////////////////////////
+    ///////////////////////// Rendering props //////////////////////////
+    ////////////////////////////////////////////////////////////////////
+
+    // Props are already in world coordinates, so they are displaced by
+    // their own model matrices instead
+    webgl::uniform3f(&gl_state.terrain.displacement_uni_loc, 0.0, 0.0, 0.0);
+
+    for (prop, pose) in map_state
+        .props
+        .iter()
+        .zip(physics::interpolated_prop_poses())
+    {
+        let (mesh, scale) = match prop.shape {
+            PropShape::Cuboid(half_extents) => (
+                &gl_state.props.box_mesh,
+                na::Vector3::new(
+                    half_extents[0],
+                    half_extents[1],
+                    half_extents[2],
+                ),
+            ),
+            PropShape::Ball(radius) => (
+                &gl_state.props.sphere_mesh,
+                na::Vector3::repeat(radius),
+            ),
+        };
+        let model = pose.to_homogeneous().prepend_nonuniform_scaling(&scale);
+
+        webgl::uniform_matrix4fv(
+            &gl_state.terrain.world_uni_loc,
+            model.as_slice(),
+        );
+        webgl::uniform_matrix4fv(
+            &gl_state.terrain.world_view_proj_uni_loc,
+            (proj * camera * model).as_slice(),
+        );
+
+        let color = map::RgbColor::from_byte_color(prop.color);
+        webgl::uniform3f(
+            &gl_state.terrain.color_uni_loc,
+            color.r(),
+            color.g(),
+            color.b(),
+        );
+
+        mesh.draw();
+    }
+
+    ////////////////////////////////////////////////////////////////////
+    ///////////////////////// Rendering player /////////////////////////
+    ////////////////////////////////////////////////////////////////////
+
+    // Only visible from the outside, since the first-person camera is inside
+    // of it
+    if camera::mode() != CameraMode::FirstPerson {
+        let model = na::Matrix4::new_translation(&player_com.coords);
+
+        webgl::uniform_matrix4fv(
+            &gl_state.terrain.world_uni_loc,
+            model.as_slice(),
+        );
+        webgl::uniform_matrix4fv(
+            &gl_state.terrain.world_view_proj_uni_loc,
+            (proj * camera * model).as_slice(),
+        );
+        webgl::uniform3f(
+            &gl_state.terrain.color_uni_loc,
+            PLAYER_COLOR[0],
+            PLAYER_COLOR[1],
+            PLAYER_COLOR[2],
+        );
+
+        gl_state.player.draw();
+    }
+
     ////////////////////////////////////////////////////////////////////
     ///////////////////////// Rendering skybox /////////////////////////
     ////////////////////////////////////////////////////////////////////
@@ -0,0 +1,103 @@
+use bincode;
+use error::Error;
+use std::{f32::consts::PI, sync::Mutex};
+
+/// Player-adjustable settings that take effect as soon as they are set.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+pub struct Settings {
+    /// Radians turned per pixel of horizontal mouse movement.
+    pub sensitivity_x: f32,
+    /// Radians turned per pixel of vertical mouse movement.
+    pub sensitivity_y: f32,
+    /// Whether moving the mouse or look stick up looks down, rather than up.
+    pub invert_y:      bool,
+    /// Fraction, from `0.0` up to but not including `1.0`, of mouse movement
+    /// that is held back each 60th of a second to be applied later. `0.0`
+    /// turns the view as soon as the mouse moves.
+    pub smoothing:     f32,
+    /// How much faster the view turns, per pixel of mouse movement within a
+    /// single event. `0.0` turns at the same rate regardless of how fast the
+    /// mouse moves.
+    pub acceleration:  f32,
+    /// Vertical field of view, in radians.
+    pub fov:           f32,
+}
+
+lazy_static! {
+    static ref SETTINGS: Mutex<Settings> = Mutex::new(Settings::default());
+}
+
+#[inline]
+pub fn settings() -> Settings {
+    *SETTINGS.lock().unwrap()
+}
+
+/// Replaces the current settings, as long as `settings` are valid.
+pub fn set_settings(settings: Settings) -> Result<(), Error> {
+    settings.validate()?;
+    *SETTINGS.lock().unwrap() = settings;
+
+    Ok(())
+}
+
+impl Settings {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            sensitivity_x: 1.0 / 128.0,
+            sensitivity_y: 1.0 / 128.0,
+            invert_y:      false,
+            smoothing:     0.0,
+            acceleration:  0.0,
+            fov:           0.875,
+        }
+    }
+
+    #[inline]
+    pub fn from_raw_data(data: &[u8]) -> Result<Self, Error> {
+        let settings: Self = bincode::deserialize(data)?;
+        settings.validate()?;
+
+        Ok(settings)
+    }
+
+    #[inline]
+    pub fn to_raw_data(&self) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(self)
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if !(self.sensitivity_x > 0.0 && self.sensitivity_y > 0.0) {
+            return Err(Error::Logic(
+                "Mouse sensitivity must be positive".to_owned(),
+            ));
+        }
+        if !(self.smoothing >= 0.0 && self.smoothing < 1.0) {
+            return Err(Error::Logic(format!(
+                "Mouse smoothing must be in [0, 1), got {}",
+                self.smoothing,
+            )));
+        }
+        if !(self.acceleration >= 0.0) {
+            return Err(Error::Logic(format!(
+                "Mouse acceleration must not be negative, got {}",
+                self.acceleration,
+            )));
+        }
+        if !(self.fov > 0.0 && self.fov < PI) {
+            return Err(Error::Logic(format!(
+                "Field of view must be in (0, π), got {}",
+                self.fov,
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Settings {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,309 @@
+//! Collision-only version of `benches/terrain_collider.rs`, for measuring
+//! per-hex against merged terrain colliders without the nphysics fork. It
+//! builds the same map and drops the same balls onto it, but uses
+//! ncollide3d's `CollisionWorld` directly: each "step" moves the balls a
+//! little and updates the broad and narrow phases, with no solver. As with
+//! the `contacts_*` benches, `steps_*` includes building the world.
+//!
+//! Run with `cargo run --release`.
+
+extern crate nalgebra as na;
+extern crate ncollide3d as nc;
+extern crate webgl_test_common;
+
+#[allow(dead_code)]
+#[path = "../../src/geometry.rs"]
+mod geometry;
+
+use nc::{
+    pipeline::{
+        CollisionGroups,
+        CollisionObjectSlabHandle,
+        CollisionWorld,
+        GeometricQueryType,
+    },
+    shape::{Ball, ShapeHandle, TriMesh},
+};
+use std::{f32::consts::FRAC_PI_2, time::Instant};
+use webgl_test_common::{
+    hex::{CubeCoord, OffsetCoord},
+    pathfind::HexHeights,
+    Hex,
+    MapData,
+    RgbByteColor,
+    SkyboxCompressed,
+    Surface,
+};
+
+const MAP_RADIUS: usize = 24;
+const BALL_COUNT: usize = 16;
+const STEPS: usize = 60;
+const RUNS: usize = 100;
+/// As in `physics`.
+const COLLIDER_MARGIN: f32 = 0.01;
+/// As in `physics`.
+const TERRAIN_SKIRT_DEPTH: f32 = 12.0;
+/// Collision group that all terrain belongs to, and ignores, since ground
+/// colliders never collide with each other in nphysics either.
+const TERRAIN_GROUP: usize = 1;
+
+type World = CollisionWorld<f32, ()>;
+
+/// A map with rolling terrain, so that plenty of hexes have exposed sides.
+fn bench_map() -> MapData {
+    let mut hexes = Vec::new();
+    for row in 0..=2 * MAP_RADIUS {
+        let row_hexes = (0..OffsetCoord::row_len(MAP_RADIUS, row))
+            .map(|col| {
+                let (x, y) =
+                    OffsetCoord::new(row, col).to_cube(MAP_RADIUS).to_pixel();
+                let height = ((x * 0.3).sin() + (y * 0.2).cos()).round() * 0.5;
+
+                Hex::new(
+                    height,
+                    RgbByteColor([0x80, 0x80, 0x80]),
+                    Surface::Grass,
+                )
+            }).collect();
+        hexes.push(row_hexes);
+    }
+
+    MapData::new(
+        MAP_RADIUS,
+        hexes,
+        0,
+        Vec::new(),
+        Vec::new(),
+        Vec::new(),
+        SkyboxCompressed::default(),
+    )
+}
+
+/// A copy of `physics::terrain_shape`, which can't be used from here since
+/// the `physics` module needs nphysics. Keep the two in step.
+fn terrain_shape<M, I>(map_data: &M, hexes: I) -> TriMesh<f32>
+where
+    M: HexHeights + ?Sized,
+    I: IntoIterator<Item = CubeCoord>,
+{
+    let mut verts = Vec::new();
+    let mut indices = Vec::new();
+
+    for cc in hexes {
+        let height = match map_data.hex_height(cc) {
+            Some(h) => h,
+            None => continue,
+        };
+        let (x, y) = cc.to_pixel();
+        let corner = |k: usize, h: f32| {
+            let c = geometry::HEXAGON[k % 6];
+            na::Point3::new(x + c[0], h, -(y + c[1]))
+        };
+
+        // Top face
+        let center_ix = verts.len();
+        verts.push(na::Point3::new(x, height, -y));
+        for k in 0..6 {
+            verts.push(corner(k, height));
+        }
+        for k in 0..6 {
+            indices.push(na::Point3::new(
+                center_ix,
+                center_ix + 1 + k,
+                center_ix + 1 + (k + 1) % 6,
+            ));
+        }
+
+        // Sides
+        for (k, &dir) in geometry::HEXAGON_EDGE_DIRS.iter().enumerate() {
+            let bottom = match map_data.hex_height(cc.neighbor(dir)) {
+                Some(nh) if nh >= height => continue,
+                Some(nh) => nh,
+                None => height - TERRAIN_SKIRT_DEPTH,
+            };
+
+            let side_ix = verts.len();
+            verts.push(corner(k, height));
+            verts.push(corner(k, bottom));
+            verts.push(corner(k + 1, bottom));
+            verts.push(corner(k + 1, height));
+            indices.push(na::Point3::new(side_ix, side_ix + 1, side_ix + 2));
+            indices.push(na::Point3::new(side_ix, side_ix + 2, side_ix + 3));
+        }
+    }
+
+    TriMesh::new(verts, indices, None)
+}
+
+fn terrain_groups() -> CollisionGroups {
+    let mut groups = CollisionGroups::new();
+    groups.set_membership(&[TERRAIN_GROUP]);
+    groups.set_blacklist(&[TERRAIN_GROUP]);
+
+    groups
+}
+
+#[inline]
+fn contacts_query() -> GeometricQueryType<f32> {
+    GeometricQueryType::Contacts(2.0 * COLLIDER_MARGIN, 0.0)
+}
+
+/// The way that terrain colliders used to be built: a separate prism-shaped
+/// `TriMesh` per hex.
+fn add_per_hex_colliders(world: &mut World, map_data: &MapData) {
+    let hex_prism_verts = geometry::HEXAGONAL_PRISM_VERTS
+        .chunks(3)
+        .map(|s| na::Point3::new(s[0], s[1], s[2]))
+        .collect::<Vec<_>>();
+    let hex_prism_indices = geometry::HEXAGONAL_PRISM_INDICES
+        .chunks(3)
+        .map(|s| na::Point3::new(s[0], s[1], s[2]))
+        .collect::<Vec<_>>();
+
+    for cc in map_data.iter_coords() {
+        let hex = map_data.get_hex(cc).unwrap();
+        let (x, y) = cc.to_pixel();
+
+        world.add(
+            na::Isometry3::new(
+                na::Vector3::new(x, hex.height, -y),
+                na::Vector3::new(-FRAC_PI_2, 0.0, 0.0),
+            ),
+            ShapeHandle::new(TriMesh::new(
+                hex_prism_verts.clone(),
+                hex_prism_indices.clone(),
+                None,
+            )),
+            terrain_groups(),
+            contacts_query(),
+            (),
+        );
+    }
+}
+
+fn add_merged_collider(world: &mut World, map_data: &MapData) {
+    world.add(
+        na::Isometry3::identity(),
+        ShapeHandle::new(terrain_shape(map_data, map_data.iter_coords())),
+        terrain_groups(),
+        contacts_query(),
+        (),
+    );
+}
+
+/// Places `BALL_COUNT` balls resting on the terrain, spread out across the
+/// map, returning their handles and starting positions.
+fn add_balls(
+    world: &mut World,
+    map_data: &MapData,
+) -> Vec<(CollisionObjectSlabHandle, na::Point3<f32>)> {
+    let ball = ShapeHandle::new(Ball::new(0.5));
+    let hex_count = map_data.iter_coords().count();
+    let mut groups = CollisionGroups::new();
+    groups.set_membership(&[0]);
+
+    map_data
+        .iter_coords()
+        .step_by(hex_count / BALL_COUNT)
+        .take(BALL_COUNT)
+        .map(|cc| {
+            let (x, y) = cc.to_pixel();
+            let height = map_data.get_hex(cc).unwrap().height;
+            let pos = na::Point3::new(x + 0.3, height + 0.5, -y);
+            let (handle, _) = world.add(
+                na::Isometry3::new(pos.coords, na::zero()),
+                ball.clone(),
+                groups,
+                contacts_query(),
+                (),
+            );
+
+            (handle, pos)
+        }).collect()
+}
+
+/// Builds a world with the terrain, and updates it once, as adding it to
+/// an nphysics world and taking the first step would.
+fn construct(map_data: &MapData, merged: bool) -> World {
+    let mut world = CollisionWorld::new(COLLIDER_MARGIN);
+    if merged {
+        add_merged_collider(&mut world, map_data);
+    } else {
+        add_per_hex_colliders(&mut world, map_data);
+    }
+    world.update();
+
+    world
+}
+
+/// Runs `STEPS` steps with the balls sliding slowly across the terrain,
+/// returning the total number of contact pairs seen.
+fn run_steps(map_data: &MapData, merged: bool) -> usize {
+    let mut world = construct(map_data, merged);
+    let balls = add_balls(&mut world, map_data);
+    world.update();
+
+    let mut contact_pairs = 0;
+    for i in 0..STEPS {
+        let offset = na::Vector3::new(0.01, -0.002, 0.0) * i as f32;
+        for &(handle, pos) in &balls {
+            world.set_position(
+                handle,
+                na::Isometry3::new((pos + offset).coords, na::zero()),
+            );
+        }
+        world.update();
+        contact_pairs += world.contact_pairs(true).count();
+    }
+
+    contact_pairs
+}
+
+/// Runs `f` `RUNS` times, printing the median and fastest run time along
+/// with what the last run returned.
+fn time<T, F>(name: &str, mut f: F)
+where
+    T: std::fmt::Debug,
+    F: FnMut() -> T,
+{
+    let mut samples = Vec::with_capacity(RUNS);
+    let mut last = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        last = Some(f());
+        let elapsed = start.elapsed();
+        samples.push(
+            elapsed.as_secs() as f64 * 1.0e3
+                + f64::from(elapsed.subsec_nanos()) * 1.0e-6,
+        );
+    }
+    samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    println!(
+        "{:<18} median {:>7.2} ms   min {:>7.2} ms   ({:?})",
+        name,
+        samples[RUNS / 2],
+        samples[0],
+        last.unwrap(),
+    );
+}
+
+fn main() {
+    let map_data = bench_map();
+    println!(
+        "{} hexes, {} balls, {} steps, {} runs each",
+        map_data.iter_coords().count(),
+        BALL_COUNT,
+        STEPS,
+        RUNS,
+    );
+
+    time("construct_per_hex", || {
+        construct(&map_data, false).collision_objects().count()
+    });
+    time("construct_merged", || {
+        construct(&map_data, true).collision_objects().count()
+    });
+    time("steps_per_hex", || run_steps(&map_data, false));
+    time("steps_merged", || run_steps(&map_data, true));
+}
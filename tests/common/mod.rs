@@ -0,0 +1,69 @@
+//! Helpers shared by the integration tests, which all drive the physics
+//! simulation without a browser.
+
+use na;
+use std::sync::{Mutex, MutexGuard};
+use webgl_test::{map, physics};
+use webgl_test_common::{
+    hex::OffsetCoord,
+    Hex,
+    MapData,
+    Prop,
+    RgbByteColor,
+    SkyboxCompressed,
+    Surface,
+};
+
+lazy_static! {
+    /// The simulation is global, so tests within the same binary must not
+    /// run at the same time.
+    static ref SIMULATION: Mutex<()> = Mutex::new(());
+}
+
+/// Takes hold of the simulation for the rest of a test.
+pub fn lock_simulation() -> MutexGuard<'static, ()> {
+    SIMULATION.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A flat map of the given `radius`, with `props` on it.
+pub fn flat_map(radius: usize, props: Vec<Prop>) -> MapData {
+    let hexes = (0..=2 * radius)
+        .map(|row| {
+            (0..OffsetCoord::row_len(radius, row))
+                .map(|_| {
+                    Hex::new(
+                        0.0,
+                        RgbByteColor([0x80, 0x80, 0x80]),
+                        Surface::Grass,
+                    )
+                }).collect()
+        }).collect();
+
+    MapData::new(
+        radius,
+        hexes,
+        0,
+        Vec::new(),
+        Vec::new(),
+        props,
+        SkyboxCompressed::default(),
+    )
+}
+
+/// Loads `map_data` and puts the player on its default spawn point, with the
+/// default character settings, returning where they spawned.
+pub fn load(map_data: &MapData) -> na::Point3<f32> {
+    // Simulated time doesn't depend on the clock, so any clock will do
+    physics::set_clock(|| 0.0);
+    physics::set_character_settings(physics::CharacterSettings::default());
+
+    let mut map_state = map::MAP.lock().unwrap();
+    *map_state = map::Map::from_map_data_without_skybox(map_data);
+    let spawn_pos = physics::spawn_position(
+        &map_state,
+        &map_state.default_spawn_point(),
+    ).expect("The default spawn point is always on the map");
+    physics::init_world(&map_state, &spawn_pos);
+
+    spawn_pos
+}
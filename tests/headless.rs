@@ -0,0 +1,277 @@
+//! Runs the physics simulation for thousands of steps without a browser,
+//! checking that the player walks, jumps, and respawns as they should.
+
+extern crate nalgebra as na;
+#[macro_use]
+extern crate lazy_static;
+extern crate webgl_test;
+extern crate webgl_test_common;
+
+mod common;
+
+use webgl_test::physics;
+
+/// Steps for the player to come to rest after spawning, slightly above the
+/// ground.
+const SETTLE_STEPS: usize = 60;
+
+fn player_position() -> na::Point3<f32> {
+    let p = physics::snapshot().player.position;
+
+    na::Point3::new(p[0], p[1], p[2])
+}
+
+/// Runs `steps` physics steps, returning the player's position after each.
+fn run(steps: usize) -> Vec<na::Point3<f32>> {
+    (0..steps)
+        .map(|_| {
+            physics::step();
+
+            player_position()
+        }).collect()
+}
+
+#[inline]
+fn horizontal_distance(a: &na::Point3<f32>, b: &na::Point3<f32>) -> f32 {
+    na::Vector2::new(a[0] - b[0], a[2] - b[2]).norm()
+}
+
+#[test]
+fn walks_around_a_square() {
+    const LEG_STEPS: usize = 150;
+
+    let _simulation = common::lock_simulation();
+    let spawn_pos = common::load(&common::flat_map(24, Vec::new()));
+    physics::set_move_direction(na::zero());
+    run(SETTLE_STEPS);
+    assert!(physics::is_grounded(), "The player should land after spawning");
+    let rest_height = player_position()[1];
+    let dropped = spawn_pos[1] - rest_height;
+    assert!((dropped - physics::SPAWN_CLEARANCE).abs() < 0.05);
+
+    let max_speed = physics::character_settings().max_speed;
+    let dt = physics::TIMESTEP;
+    let legs = [
+        -na::Vector3::z(),
+        na::Vector3::x(),
+        na::Vector3::z(),
+        -na::Vector3::x(),
+    ];
+    for dir in legs.iter() {
+        let start = player_position();
+        physics::set_move_direction(*dir);
+        let trajectory = run(LEG_STEPS);
+        physics::set_move_direction(na::zero());
+        let trajectory = [trajectory, run(SETTLE_STEPS)].concat();
+
+        let mut prev = start;
+        for pos in &trajectory {
+            assert!(
+                (pos[1] - rest_height).abs() < 0.05,
+                "The player should stay on the ground, but is at {}",
+                pos,
+            );
+            // Never moving backwards, nor faster than walking speed
+            let moved = (pos - prev).dot(dir);
+            assert!(moved > -1.0e-4);
+            assert!(moved <= max_speed * dt * 1.01);
+            // Nor sideways
+            let sideways = (pos - start) - dir * (pos - start).dot(dir);
+            assert!(na::Vector2::new(sideways[0], sideways[2]).norm() < 0.05);
+
+            prev = *pos;
+        }
+
+        // Walking for `LEG_STEPS` steps, less a little for getting up to
+        // speed and slowing down
+        let walked = (trajectory[trajectory.len() - 1] - start).dot(dir);
+        let expected = max_speed * dt * LEG_STEPS as f32;
+        assert!(
+            walked > 0.9 * expected && walked < 1.1 * expected,
+            "Walked {}, expected about {}",
+            walked,
+            expected,
+        );
+    }
+
+    // Back where they started, having walked around a square
+    let end = player_position();
+    assert!(horizontal_distance(&end, &spawn_pos) < 0.5);
+    assert!(physics::drain_respawn_events().is_empty());
+}
+
+#[test]
+fn jumps_and_lands() {
+    const JUMPS: usize = 10;
+    const JUMP_STEPS: usize = 120;
+
+    let _simulation = common::lock_simulation();
+    common::load(&common::flat_map(8, Vec::new()));
+    physics::set_move_direction(na::zero());
+    run(SETTLE_STEPS);
+    let rest = player_position();
+
+    let jump_speed = physics::character_settings().jump_speed;
+    let expected_rise =
+        jump_speed * jump_speed / (2.0 * physics::STANDARD_GRAVITY);
+    let expected_air_steps = (2.0 * jump_speed
+        / physics::STANDARD_GRAVITY
+        / physics::TIMESTEP) as usize;
+    for _ in 0..JUMPS {
+        physics::jump();
+        let trajectory = run(JUMP_STEPS);
+
+        let peak = trajectory
+            .iter()
+            .map(|pos| pos[1])
+            .fold(rest[1], f32::max);
+        let rise = peak - rest[1];
+        assert!(
+            (rise - expected_rise).abs() < 0.15 * expected_rise,
+            "Rose {}, expected about {}",
+            rise,
+            expected_rise,
+        );
+
+        let landed = trajectory
+            .iter()
+            .skip(1)
+            .position(|pos| pos[1] <= rest[1] + 0.05)
+            .expect("The player should come back down")
+            + 1;
+        assert!(
+            landed.max(expected_air_steps) - landed.min(expected_air_steps)
+                < 10,
+            "In the air for {} steps, expected about {}",
+            landed,
+            expected_air_steps,
+        );
+
+        // Straight up and down, and back at rest
+        for pos in &trajectory {
+            assert!(horizontal_distance(pos, &rest) < 0.01);
+        }
+        assert!(physics::is_grounded());
+        assert!((player_position()[1] - rest[1]).abs() < 0.05);
+    }
+
+    // Jumping in the air does nothing
+    physics::jump();
+    run(20);
+    let height = player_position()[1];
+    physics::jump();
+    run(1);
+    assert!(player_position()[1] <= height + 0.05);
+}
+
+#[test]
+fn jump_reaches_full_height_at_default_settings() {
+    let _simulation = common::lock_simulation();
+    common::load(&common::flat_map(8, Vec::new()));
+    physics::set_character_settings(physics::CharacterSettings::default());
+    physics::set_move_direction(na::zero());
+    run(SETTLE_STEPS);
+    let rest = player_position();
+
+    physics::jump();
+    let mut trajectory = run(2);
+    assert!(!physics::is_grounded(), "Should have left the ground");
+    trajectory.extend(run(60));
+
+    let jump_speed = physics::CharacterSettings::default().jump_speed;
+    let apex = trajectory
+        .iter()
+        .map(|pos| pos[1] - rest[1])
+        .fold(0.0, f32::max);
+    let expected_apex =
+        jump_speed * jump_speed / (2.0 * physics::STANDARD_GRAVITY);
+    // Nowhere near low enough to still be snapped to the ground
+    assert!(apex > 2.0 * physics::GROUND_TOLERANCE);
+    assert!(
+        (apex - expected_apex).abs() < 0.1,
+        "Apex of {}, expected about {}",
+        apex,
+        expected_apex,
+    );
+}
+
+#[test]
+fn jump_height_follows_jump_speed() {
+    const JUMP_STEPS: usize = 120;
+
+    let _simulation = common::lock_simulation();
+    common::load(&common::flat_map(8, Vec::new()));
+    physics::set_move_direction(na::zero());
+    run(SETTLE_STEPS);
+    let rest = player_position();
+
+    for &jump_speed in &[1.5, 3.0, 5.0, 8.0] {
+        physics::set_character_settings(physics::CharacterSettings {
+            jump_speed,
+            ..physics::CharacterSettings::default()
+        });
+        assert!(physics::is_grounded());
+        physics::jump();
+        let trajectory = run(JUMP_STEPS);
+
+        let gained = trajectory
+            .iter()
+            .map(|pos| pos[1] - rest[1])
+            .fold(0.0, f32::max);
+        let expected =
+            jump_speed * jump_speed / (2.0 * physics::STANDARD_GRAVITY);
+        assert!(
+            (gained - expected).abs() < 0.1 * expected + 0.02,
+            "Jumping at {} m/s gained {}, expected about {}",
+            jump_speed,
+            gained,
+            expected,
+        );
+        assert!((player_position()[1] - rest[1]).abs() < 0.05);
+    }
+}
+
+#[test]
+fn respawns_after_walking_off_the_edge() {
+    const STEPS: usize = 3000;
+
+    let _simulation = common::lock_simulation();
+    let spawn_pos = common::load(&common::flat_map(4, Vec::new()));
+    physics::drain_respawn_events();
+    physics::set_move_direction(-na::Vector3::z());
+
+    let kill_height = physics::kill_height();
+    let mut respawns = 0;
+    let mut lowest = spawn_pos[1];
+    for _ in 0..STEPS {
+        physics::step();
+        let pos = player_position();
+        assert!(pos.coords.iter().all(|c| c.is_finite()));
+        lowest = lowest.min(pos[1]);
+
+        let events = physics::drain_respawn_events();
+        assert!(events.len() <= 1);
+        if let Some(event) = events.first() {
+            respawns += 1;
+            assert!(event.fell_from[1] < kill_height);
+            // Walked off of the edge in front, rather than falling through
+            // the ground
+            assert!(horizontal_distance(&event.fell_from, &spawn_pos) > 4.0);
+            assert_eq!(pos, spawn_pos, "Should respawn on the spawn point");
+        } else {
+            // Never falls far past the kill plane before respawning
+            assert!(pos[1] > kill_height - 1.0);
+        }
+    }
+
+    assert!(lowest < kill_height);
+    // Each time round takes well under five simulated seconds: walking off
+    // of the map, then falling to the kill plane
+    let expected = (STEPS as f32 * physics::TIMESTEP / 5.0) as usize;
+    assert!(
+        respawns >= expected,
+        "Respawned {} times, expected at least {}",
+        respawns,
+        expected,
+    );
+}
@@ -0,0 +1,91 @@
+extern crate nalgebra as na;
+#[macro_use]
+extern crate lazy_static;
+extern crate webgl_test;
+extern crate webgl_test_common;
+
+mod common;
+
+use webgl_test::physics::{self, PhysicsSnapshot};
+use webgl_test_common::{
+    hex::CubeCoord,
+    Prop,
+    PropShape,
+    RgbByteColor,
+};
+
+const MAP_RADIUS: usize = 12;
+const STEPS: usize = 600;
+
+/// Props in a line in front of the spawn hex, for the player to walk into.
+fn props() -> Vec<Prop> {
+    let (x, y) = CubeCoord::map_center(MAP_RADIUS).to_pixel();
+
+    (0..4)
+        .map(|i| {
+            let size = 0.3 + 0.05 * i as f32;
+            let shape = if i % 2 == 0 {
+                PropShape::Cuboid([size, size, size])
+            } else {
+                PropShape::Ball(size)
+            };
+
+            Prop::new(
+                shape,
+                0.5,
+                [x + 0.1 * i as f32, size + 0.5, -y - 2.0 - 1.5 * i as f32],
+                RgbByteColor([0x80, 0x40, 0x20]),
+            )
+        }).collect()
+}
+
+/// Walks forwards, jumping every so often, and records every snapshot.
+fn play(steps: usize) -> Vec<PhysicsSnapshot> {
+    (0..steps)
+        .map(|i| {
+            if i % 90 == 45 {
+                physics::jump();
+            }
+            physics::step();
+
+            physics::snapshot()
+        }).collect()
+}
+
+#[test]
+fn restored_world_plays_out_the_same() {
+    let _simulation = common::lock_simulation();
+    common::load(&common::flat_map(MAP_RADIUS, props()));
+
+    // Build up contacts between the player, the props, and the ground before
+    // taking the snapshot
+    physics::set_move_direction(-na::Vector3::z());
+    play(60);
+    let start = physics::snapshot();
+
+    physics::restore(&start).unwrap();
+    assert_eq!(physics::snapshot(), start);
+    let first = play(STEPS);
+
+    physics::restore(&start).unwrap();
+    assert_eq!(physics::snapshot(), start);
+    let second = play(STEPS);
+
+    assert_ne!(first.last(), Some(&start));
+    for (i, (a, b)) in first.iter().zip(&second).enumerate() {
+        assert_eq!(a, b, "Replays diverged after {} steps", i + 1);
+    }
+}
+
+#[test]
+fn snapshot_round_trips_through_raw_data() {
+    let _simulation = common::lock_simulation();
+    common::load(&common::flat_map(MAP_RADIUS, props()));
+    physics::set_move_direction(-na::Vector3::z());
+    play(30);
+
+    let snapshot = physics::snapshot();
+    let raw = snapshot.to_raw_data().unwrap();
+
+    assert_eq!(PhysicsSnapshot::from_raw_data(&raw).unwrap(), snapshot);
+}
@@ -0,0 +1,767 @@
+//! Hexagonal grid coordinates shared by the client and the server.
+//!
+//! Hexes are "flat-topped" and have a circumradius of `1.0`. Cube coordinates
+//! are stored as `(x, y, z)` with the invariant `x + y + z == 0`; axial
+//! coordinates are `(q, r) == (x, z)`. Map storage (see `MapData`) is a
+//! hexagon of radius `radius` laid out in rows of varying length, with the
+//! central hex at axial `(radius, radius)`; `OffsetCoord` is an index into
+//! that storage.
+
+use std::ops::{Add, Mul, Neg, Sub};
+
+pub const SQRT_3_ON_2: f32 = 0.866_025_4;
+pub const SQRT_3: f32 = 1.732_050_8;
+
+#[derive(
+    Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug, Hash, Default,
+)]
+pub struct CubeCoord {
+    pub x: isize,
+    pub y: isize,
+    pub z: isize,
+}
+
+#[derive(
+    Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug, Hash, Default,
+)]
+pub struct AxialCoord {
+    pub q: isize,
+    pub r: isize,
+}
+
+/// Index into the rows of a map of some given radius, i.e.
+/// `hexes[row][col]`.
+#[derive(
+    Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug, Hash, Default,
+)]
+pub struct OffsetCoord {
+    pub row: usize,
+    pub col: usize,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug, Hash)]
+#[repr(u8)]
+pub enum HexDir {
+    Southeast = 0,
+    Northeast = 1,
+    North = 2,
+    Northwest = 3,
+    Southwest = 4,
+    South = 5,
+}
+
+/// Iterates over the hexes at exactly `radius` steps away from a center hex.
+#[derive(Clone, Debug)]
+pub struct CubeRing {
+    cube:   CubeCoord,
+    radius: usize,
+    i:      u8,
+    j:      usize,
+}
+
+/// Iterates over the center hex and then every ring around it, up to and
+/// including `radius`.
+#[derive(Clone, Debug)]
+pub struct CubeSpiral {
+    center: CubeCoord,
+    radius: usize,
+    ring:   CubeRing,
+}
+
+impl CubeCoord {
+    #[inline]
+    pub fn new(x: isize, y: isize, z: isize) -> Self {
+        debug_assert_eq!(x + y + z, 0);
+
+        Self { x, y, z }
+    }
+
+    #[inline]
+    pub fn origin() -> Self {
+        Self::default()
+    }
+
+    /// The central hex of a map with the given `radius`.
+    #[inline]
+    pub fn map_center(radius: usize) -> Self {
+        let rad = radius as isize;
+
+        AxialCoord::new(rad, rad).to_cube()
+    }
+
+    #[inline]
+    pub fn to_axial(self) -> AxialCoord {
+        AxialCoord::new(self.x, self.z)
+    }
+
+    #[inline]
+    pub fn to_offset(self, radius: usize) -> Option<OffsetCoord> {
+        OffsetCoord::from_cube(self, radius)
+    }
+
+    #[inline]
+    pub fn neighbor(self, dir: HexDir) -> Self {
+        self + dir.to_cube()
+    }
+
+    #[inline]
+    pub fn neighbors(self) -> [Self; 6] {
+        let mut ns = [self; 6];
+        for (n, &dir) in ns.iter_mut().zip(HexDir::ALL.iter()) {
+            *n = self.neighbor(dir);
+        }
+
+        ns
+    }
+
+    /// Number of steps away from `(0, 0, 0)`.
+    #[inline]
+    pub fn length(self) -> usize {
+        ((self.x.abs() + self.y.abs() + self.z.abs()) / 2) as usize
+    }
+
+    #[inline]
+    pub fn distance(self, other: Self) -> usize {
+        (self - other).length()
+    }
+
+    #[inline]
+    pub fn ring(self, radius: usize) -> CubeRing {
+        CubeRing::new(self, radius)
+    }
+
+    #[inline]
+    pub fn spiral(self, radius: usize) -> CubeSpiral {
+        CubeSpiral::new(self, radius)
+    }
+
+    /// Rounds fractional cube coordinates to the hex that contains them.
+    pub fn round(x: f32, y: f32, z: f32) -> Self {
+        let rx = x.round();
+        let ry = y.round();
+        let rz = z.round();
+
+        let x_diff = (rx - x).abs();
+        let y_diff = (ry - y).abs();
+        let z_diff = (rz - z).abs();
+
+        if x_diff > y_diff && x_diff > z_diff {
+            let (ry, rz) = (ry as isize, rz as isize);
+
+            Self::new(-(ry + rz), ry, rz)
+        } else if y_diff > z_diff {
+            let (rx, rz) = (rx as isize, rz as isize);
+
+            Self::new(rx, -(rx + rz), rz)
+        } else {
+            let (rx, ry) = (rx as isize, ry as isize);
+
+            Self::new(rx, ry, -(rx + ry))
+        }
+    }
+
+    /// The hex containing the given point on the plane.
+    #[inline]
+    pub fn from_pixel(x: f32, y: f32) -> Self {
+        const SQRT_3_ON_3: f32 = 0.577_350_26;
+        const TWO_ON_3: f32 = 2.0 / 3.0;
+        const NEG_1_ON_3: f32 = -1.0 / 3.0;
+
+        let q = TWO_ON_3 * x;
+        let r = SQRT_3_ON_3.mul_add(y, NEG_1_ON_3 * x);
+
+        Self::round(q, -(q + r), r)
+    }
+
+    /// The center of this hex on the plane.
+    #[inline]
+    pub fn to_pixel(self) -> (f32, f32) {
+        self.to_axial().to_pixel()
+    }
+}
+
+impl Add for CubeCoord {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        Self::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for CubeCoord {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<isize> for CubeCoord {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, a: isize) -> Self {
+        Self::new(self.x * a, self.y * a, self.z * a)
+    }
+}
+
+impl Neg for CubeCoord {
+    type Output = Self;
+
+    #[inline]
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl From<AxialCoord> for CubeCoord {
+    #[inline]
+    fn from(ac: AxialCoord) -> Self {
+        ac.to_cube()
+    }
+}
+
+impl AxialCoord {
+    #[inline]
+    pub fn new(q: isize, r: isize) -> Self {
+        Self { q, r }
+    }
+
+    #[inline]
+    pub fn to_cube(self) -> CubeCoord {
+        CubeCoord::new(self.q, -(self.q + self.r), self.r)
+    }
+
+    #[inline]
+    pub fn to_pixel(self) -> (f32, f32) {
+        let (q, r) = (self.q as f32, self.r as f32);
+
+        (1.5 * q, SQRT_3_ON_2.mul_add(q, SQRT_3 * r))
+    }
+}
+
+impl From<CubeCoord> for AxialCoord {
+    #[inline]
+    fn from(cc: CubeCoord) -> Self {
+        cc.to_axial()
+    }
+}
+
+impl OffsetCoord {
+    #[inline]
+    pub fn new(row: usize, col: usize) -> Self {
+        Self { row, col }
+    }
+
+    /// Returns `None` if `cc` lies outside of a map with the given `radius`.
+    pub fn from_cube(cc: CubeCoord, radius: usize) -> Option<Self> {
+        if cc.distance(CubeCoord::map_center(radius)) > radius {
+            return None;
+        }
+
+        let row = cc.z as usize;
+
+        Some(Self::new(row, cc.x as usize - radius.saturating_sub(row)))
+    }
+
+    #[inline]
+    pub fn to_cube(self, radius: usize) -> CubeCoord {
+        AxialCoord::new(
+            (self.col + radius.saturating_sub(self.row)) as isize,
+            self.row as isize,
+        ).to_cube()
+    }
+
+    /// Number of hexes in the given `row` of a map with the given `radius`.
+    #[inline]
+    pub fn row_len(radius: usize, row: usize) -> usize {
+        (2 * radius + 1).saturating_sub(row.abs_diff(radius))
+    }
+}
+
+impl HexDir {
+    pub const ALL: [HexDir; 6] = [
+        HexDir::Southeast,
+        HexDir::Northeast,
+        HexDir::North,
+        HexDir::Northwest,
+        HexDir::Southwest,
+        HexDir::South,
+    ];
+
+    #[inline]
+    pub fn from_u8(b: u8) -> Option<Self> {
+        HexDir::ALL.get(b as usize).cloned()
+    }
+
+    #[inline]
+    pub fn to_cube(self) -> CubeCoord {
+        match self {
+            HexDir::Southeast => CubeCoord::new(1, -1, 0),
+            HexDir::Northeast => CubeCoord::new(1, 0, -1),
+            HexDir::North => CubeCoord::new(0, 1, -1),
+            HexDir::Northwest => CubeCoord::new(-1, 1, 0),
+            HexDir::Southwest => CubeCoord::new(-1, 0, 1),
+            HexDir::South => CubeCoord::new(0, -1, 1),
+        }
+    }
+
+    #[inline]
+    pub fn opposite(self) -> Self {
+        HexDir::ALL[(self as usize + 3) % 6]
+    }
+}
+
+impl CubeRing {
+    #[inline]
+    pub fn new(center: CubeCoord, radius: usize) -> Self {
+        Self {
+            cube: center + HexDir::Southwest.to_cube() * radius as isize,
+            radius,
+            i: 0,
+            j: 0,
+        }
+    }
+
+    #[inline]
+    pub fn get_radius(&self) -> usize {
+        self.radius
+    }
+}
+
+impl Iterator for CubeRing {
+    type Item = CubeCoord;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.i >= 6 {
+            return None;
+        }
+
+        let ret = self.cube;
+        if self.radius == 0 {
+            self.i = 6;
+
+            return Some(ret);
+        }
+
+        self.cube = self.cube.neighbor(HexDir::ALL[self.i as usize]);
+        self.j += 1;
+        if self.j == self.radius {
+            self.j = 0;
+            self.i += 1;
+        }
+
+        Some(ret)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = if self.i >= 6 {
+            0
+        } else if self.radius == 0 {
+            1
+        } else {
+            (6 - self.i as usize) * self.radius - self.j
+        };
+
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for CubeRing {}
+
+impl CubeSpiral {
+    #[inline]
+    pub fn new(center: CubeCoord, radius: usize) -> Self {
+        Self {
+            center,
+            radius,
+            ring: CubeRing::new(center, 0),
+        }
+    }
+}
+
+impl Iterator for CubeSpiral {
+    type Item = CubeCoord;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(cc) = self.ring.next() {
+                return Some(cc);
+            }
+
+            let next_radius = self.ring.get_radius() + 1;
+            if next_radius > self.radius {
+                return None;
+            }
+            self.ring = CubeRing::new(self.center, next_radius);
+        }
+    }
+}
+
+#[inline]
+pub fn cube_direction(dir: HexDir) -> CubeCoord {
+    dir.to_cube()
+}
+
+#[inline]
+pub fn cube_neighbor(cc: CubeCoord, dir: HexDir) -> CubeCoord {
+    cc.neighbor(dir)
+}
+
+#[inline]
+pub fn cube_distance(a: CubeCoord, b: CubeCoord) -> usize {
+    a.distance(b)
+}
+
+#[inline]
+pub fn pixel_to_cube(x: f32, y: f32) -> CubeCoord {
+    CubeCoord::from_pixel(x, y)
+}
+
+#[inline]
+pub fn axial_to_cartesian(q: f32, r: f32) -> (f32, f32) {
+    (1.5 * q, SQRT_3_ON_2.mul_add(q, SQRT_3 * r))
+}
+
+/// All hexes on the straight line from `a` to `b`, inclusive of both ends.
+/// Consecutive hexes in the result are always neighbors, and
+/// `hex_line(b, a)` is always `hex_line(a, b)` reversed.
+pub fn hex_line(a: CubeCoord, b: CubeCoord) -> Vec<CubeCoord> {
+    // Nudging the line keeps the sampled points off of hex edges, so that
+    // rounding is never ambiguous. Sampling relative to the start keeps the
+    // coordinates small enough for the nudge to survive `f32` precision.
+    const EPSILON: f32 = 1e-4;
+
+    let n = a.distance(b);
+    if n == 0 {
+        return vec![a];
+    }
+
+    // Always draw from the same end, so that ties are broken the same way
+    // in both directions
+    let reversed = (b.x, b.y) < (a.x, a.y);
+    let (start, end) = if reversed { (b, a) } else { (a, b) };
+    let diff = end - start;
+    let (dx, dy, dz) = (diff.x as f32, diff.y as f32, diff.z as f32);
+
+    let mut line = Vec::with_capacity(n + 1);
+    for i in 0..=n {
+        let t = i as f32 / n as f32;
+        line.push(
+            start + CubeCoord::round(
+                dx.mul_add(t, EPSILON),
+                dy.mul_add(t, EPSILON),
+                dz.mul_add(t, -2.0 * EPSILON),
+            ),
+        );
+    }
+    if reversed {
+        line.reverse();
+    }
+
+    line
+}
+
+/// All hexes at most `n` steps away from `center`, including `center`.
+pub fn hexes_in_range(center: CubeCoord, n: usize) -> Vec<CubeCoord> {
+    range_intersection(&[(center, n)])
+}
+
+/// All hexes that are simultaneously within range of every one of the given
+/// `(center, n)` pairs.
+pub fn range_intersection(ranges: &[(CubeCoord, usize)]) -> Vec<CubeCoord> {
+    let mut hexes = Vec::new();
+    let (&(first_center, first_n), rest) = match ranges.split_first() {
+        Some(split) => split,
+        None => return hexes,
+    };
+
+    let first_n = first_n as isize;
+    let (mut x_min, mut x_max) =
+        (first_center.x - first_n, first_center.x + first_n);
+    let (mut y_min, mut y_max) =
+        (first_center.y - first_n, first_center.y + first_n);
+    let (mut z_min, mut z_max) =
+        (first_center.z - first_n, first_center.z + first_n);
+    for &(center, n) in rest {
+        let n = n as isize;
+        x_min = x_min.max(center.x - n);
+        x_max = x_max.min(center.x + n);
+        y_min = y_min.max(center.y - n);
+        y_max = y_max.min(center.y + n);
+        z_min = z_min.max(center.z - n);
+        z_max = z_max.min(center.z + n);
+    }
+
+    for x in x_min..=x_max {
+        let y_lo = y_min.max(-x - z_max);
+        let y_hi = y_max.min(-x - z_min);
+        for y in y_lo..=y_hi {
+            hexes.push(CubeCoord::new(x, y, -x - y));
+        }
+    }
+
+    hexes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADIUS: usize = 24;
+
+    fn map_coords() -> Vec<CubeCoord> {
+        CubeCoord::map_center(RADIUS).spiral(RADIUS).collect()
+    }
+
+    #[test]
+    fn offset_cube_round_trip() {
+        let mut count = 0;
+        for row in 0..=2 * RADIUS {
+            for col in 0..OffsetCoord::row_len(RADIUS, row) {
+                let oc = OffsetCoord::new(row, col);
+                let cc = oc.to_cube(RADIUS);
+
+                assert_eq!(cc.x + cc.y + cc.z, 0);
+                assert!(cc.distance(CubeCoord::map_center(RADIUS)) <= RADIUS);
+                assert_eq!(cc.to_offset(RADIUS), Some(oc));
+                count += 1;
+            }
+        }
+        assert_eq!(count, 3 * RADIUS * (RADIUS + 1) + 1);
+
+        for cc in map_coords() {
+            let oc = cc.to_offset(RADIUS).unwrap();
+            assert_eq!(oc.to_cube(RADIUS), cc);
+        }
+    }
+
+    #[test]
+    fn off_map_has_no_offset() {
+        let center = CubeCoord::map_center(RADIUS);
+        for cc in center.ring(RADIUS + 1) {
+            assert_eq!(cc.to_offset(RADIUS), None);
+        }
+    }
+
+    #[test]
+    fn ring_sizes() {
+        let center = CubeCoord::new(3, -5, 2);
+        assert_eq!(center.ring(0).collect::<Vec<_>>(), vec![center]);
+        assert_eq!(center.ring(0).len(), 1);
+
+        for radius in 1..=RADIUS {
+            let ring = center.ring(radius);
+            assert_eq!(ring.len(), 6 * radius);
+
+            let hexes = ring.collect::<Vec<_>>();
+            assert_eq!(hexes.len(), 6 * radius);
+            for (i, &cc) in hexes.iter().enumerate() {
+                assert_eq!(cc.distance(center), radius);
+                // Each hex in the ring neighbors the next, all the way round
+                let next = hexes[(i + 1) % hexes.len()];
+                assert_eq!(cc.distance(next), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn spiral_sizes() {
+        let center = CubeCoord::origin();
+        assert_eq!(center.spiral(0).collect::<Vec<_>>(), vec![center]);
+
+        for radius in 0..=RADIUS {
+            let hexes = center.spiral(radius).collect::<Vec<_>>();
+            assert_eq!(hexes.len(), 3 * radius * (radius + 1) + 1);
+
+            let mut sorted = hexes.clone();
+            sorted.sort_by_key(|cc| (cc.x, cc.y));
+            sorted.dedup();
+            assert_eq!(sorted.len(), hexes.len());
+
+            // Hexes come out from the center outwards
+            for pair in hexes.windows(2) {
+                assert!(pair[0].length() <= pair[1].length());
+            }
+        }
+    }
+
+    #[test]
+    fn pixel_round_trip() {
+        for cc in map_coords() {
+            let (x, y) = cc.to_pixel();
+            assert_eq!(CubeCoord::from_pixel(x, y), cc);
+        }
+    }
+
+    #[test]
+    fn round_exact() {
+        for cc in map_coords() {
+            assert_eq!(
+                CubeCoord::round(cc.x as f32, cc.y as f32, cc.z as f32),
+                cc,
+            );
+        }
+    }
+
+    #[test]
+    fn round_ties() {
+        // Halfway between two neighbors, and where three hexes meet
+        let ties = [
+            ((0.5, -0.5, 0.0), [(0, 0, 0), (1, -1, 0)].to_vec()),
+            ((-0.5, 0.0, 0.5), [(0, 0, 0), (-1, 0, 1)].to_vec()),
+            ((0.0, 0.5, -0.5), [(0, 0, 0), (0, 1, -1)].to_vec()),
+            ((2.5, -2.5, 0.0), [(2, -2, 0), (3, -3, 0)].to_vec()),
+            (
+                (1.0 / 3.0, 1.0 / 3.0, -2.0 / 3.0),
+                [(0, 0, 0), (1, 0, -1), (0, 1, -1)].to_vec(),
+            ),
+            (
+                (-1.0 / 3.0, -1.0 / 3.0, 2.0 / 3.0),
+                [(0, 0, 0), (-1, 0, 1), (0, -1, 1)].to_vec(),
+            ),
+        ];
+
+        for &((x, y, z), ref candidates) in ties.iter() {
+            let cc = CubeCoord::round(x, y, z);
+            assert_eq!(cc.x + cc.y + cc.z, 0);
+            assert!(
+                candidates.contains(&(cc.x, cc.y, cc.z)),
+                "({}, {}, {}) rounded to {:?}",
+                x,
+                y,
+                z,
+                cc,
+            );
+            // Ties are always broken the same way
+            assert_eq!(CubeCoord::round(x, y, z), cc);
+        }
+    }
+
+    #[test]
+    fn distance_symmetric() {
+        let coords = CubeCoord::origin().spiral(6).collect::<Vec<_>>();
+        for &a in &coords {
+            assert_eq!(a.distance(a), 0);
+            for &b in &coords {
+                assert_eq!(a.distance(b), b.distance(a));
+                assert_eq!(a.distance(b), (a - b).length());
+            }
+        }
+    }
+
+    #[test]
+    fn neighbors_are_adjacent() {
+        let cc = CubeCoord::new(2, -7, 5);
+        for &n in cc.neighbors().iter() {
+            assert_eq!(cc.distance(n), 1);
+        }
+    }
+
+    #[test]
+    fn opposite_dirs() {
+        for &dir in HexDir::ALL.iter() {
+            assert_ne!(dir.opposite(), dir);
+            assert_eq!(dir.opposite().opposite(), dir);
+            assert_eq!(dir.opposite().to_cube(), -dir.to_cube());
+            assert_eq!(HexDir::from_u8(dir as u8), Some(dir));
+        }
+        assert_eq!(HexDir::from_u8(6), None);
+    }
+
+    #[test]
+    fn line_endpoints_and_steps() {
+        let coords =
+            CubeCoord::map_center(RADIUS).spiral(8).collect::<Vec<_>>();
+        for &a in &coords {
+            for &b in &coords {
+                let line = hex_line(a, b);
+                assert_eq!(line.len(), a.distance(b) + 1);
+                assert_eq!(line[0], a);
+                assert_eq!(line[line.len() - 1], b);
+                for pair in line.windows(2) {
+                    assert_eq!(pair[0].distance(pair[1]), 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn line_symmetric() {
+        // Near the origin, and far from it, where `f32` precision is worse
+        let centers = [CubeCoord::origin(), CubeCoord::map_center(RADIUS)];
+        for &center in centers.iter() {
+            let coords = center.spiral(RADIUS).collect::<Vec<_>>();
+            for (i, &a) in coords.iter().enumerate() {
+                for &b in coords[i + 1..].iter().step_by(7) {
+                    let mut back = hex_line(b, a);
+                    back.reverse();
+                    assert_eq!(hex_line(a, b), back, "{:?} to {:?}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn line_along_axis() {
+        let a = CubeCoord::new(-3, 3, 0);
+        let line = hex_line(a, CubeCoord::new(3, -3, 0));
+        for (i, &cc) in line.iter().enumerate() {
+            assert_eq!(cc, a + HexDir::Southeast.to_cube() * i as isize);
+        }
+    }
+
+    #[test]
+    fn range_contents() {
+        let center = CubeCoord::new(4, -1, -3);
+        for n in 0..=6 {
+            let mut range = hexes_in_range(center, n);
+            assert_eq!(range.len(), 3 * n * (n + 1) + 1);
+            assert!(range.iter().all(|cc| cc.distance(center) <= n));
+
+            let mut spiral = center.spiral(n).collect::<Vec<_>>();
+            range.sort_by_key(|cc| (cc.x, cc.y));
+            spiral.sort_by_key(|cc| (cc.x, cc.y));
+            assert_eq!(range, spiral);
+        }
+    }
+
+    #[test]
+    fn intersection_contents() {
+        assert!(range_intersection(&[]).is_empty());
+
+        let ranges = [
+            (CubeCoord::origin(), 4),
+            (CubeCoord::new(3, -1, -2), 3),
+            (CubeCoord::new(1, 2, -3), 5),
+        ];
+        let mut expected = CubeCoord::origin()
+            .spiral(4)
+            .filter(|&cc| ranges.iter().all(|&(c, n)| cc.distance(c) <= n))
+            .collect::<Vec<_>>();
+        let mut intersection = range_intersection(&ranges);
+        assert!(!expected.is_empty());
+        expected.sort_by_key(|cc| (cc.x, cc.y));
+        intersection.sort_by_key(|cc| (cc.x, cc.y));
+        assert_eq!(intersection, expected);
+
+        // Ranges that are too far apart to overlap
+        assert!(
+            range_intersection(&[
+                (CubeCoord::origin(), 2),
+                (CubeCoord::new(6, -6, 0), 3),
+            ]).is_empty()
+        );
+        // Ranges that only just touch
+        assert_eq!(
+            range_intersection(&[
+                (CubeCoord::origin(), 2),
+                (CubeCoord::new(4, -4, 0), 2),
+            ]),
+            vec![CubeCoord::new(2, -2, 0)],
+        );
+    }
+}
@@ -3,23 +3,71 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 
+pub mod hex;
+pub mod pathfind;
+
+use hex::{CubeCoord, CubeSpiral, HexDir, OffsetCoord};
+use pathfind::HexHeights;
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct MapData {
     radius:            usize,
     hexes:             Vec<Vec<Hex>>,
+    /// Seed that the map was generated from, so that the same map can be
+    /// generated again.
+    pub seed:          u64,
     pub light_sources: Vec<LightSource>,
+    pub spawn_points:  Vec<SpawnPoint>,
+    pub props:         Vec<Prop>,
     pub skybox:        SkyboxCompressed,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct Hex {
-    pub height: f32,
-    pub color:  RgbByteColor,
+    pub height:  f32,
+    pub color:   RgbByteColor,
+    pub surface: Surface,
+}
+
+/// What the top of a hex is made of, which decides how it feels to walk on.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Surface {
+    Grass,
+    Ice,
+    Mud,
+    BouncePad,
 }
 
 #[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
 pub struct RgbByteColor(pub [u8; 3]);
 
+/// A hex that players can spawn on top of, and the direction that they face
+/// when doing so.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SpawnPoint {
+    pub hex:    CubeCoord,
+    pub facing: HexDir,
+}
+
+/// A loose object that starts out somewhere on the map, and can be pushed
+/// around from there.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+pub struct Prop {
+    pub shape:    PropShape,
+    pub mass:     f32,
+    /// Where the center of the prop starts out, in world coordinates.
+    pub position: [f32; 3],
+    pub color:    RgbByteColor,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+pub enum PropShape {
+    /// A box with the given half-extents along each axis.
+    Cuboid([f32; 3]),
+    /// A ball with the given radius.
+    Ball(f32),
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub enum LightSource {
     Directional([f32; 3]),
@@ -42,13 +90,19 @@ impl MapData {
     pub fn new(
         radius: usize,
         hexes: Vec<Vec<Hex>>,
+        seed: u64,
         light_sources: Vec<LightSource>,
+        spawn_points: Vec<SpawnPoint>,
+        props: Vec<Prop>,
         skybox: SkyboxCompressed,
     ) -> Self {
         Self {
             radius,
             hexes,
+            seed,
             light_sources,
+            spawn_points,
+            props,
             skybox,
         }
     }
@@ -67,6 +121,31 @@ impl MapData {
     pub fn get_hexes(&self) -> &Vec<Vec<Hex>> {
         &self.hexes
     }
+
+    #[inline]
+    pub fn get_hex(&self, cc: CubeCoord) -> Option<&Hex> {
+        let oc = OffsetCoord::from_cube(cc, self.radius)?;
+        self.hexes.get(oc.row).and_then(|r| r.get(oc.col))
+    }
+
+    #[inline]
+    pub fn get_hex_mut(&mut self, cc: CubeCoord) -> Option<&mut Hex> {
+        let oc = OffsetCoord::from_cube(cc, self.radius)?;
+        self.hexes.get_mut(oc.row).and_then(|r| r.get_mut(oc.col))
+    }
+
+    /// The coordinates of every hex on the map, from the center outwards.
+    #[inline]
+    pub fn iter_coords(&self) -> CubeSpiral {
+        CubeCoord::map_center(self.radius).spiral(self.radius)
+    }
+}
+
+impl HexHeights for MapData {
+    #[inline]
+    fn hex_height(&self, cc: CubeCoord) -> Option<f32> {
+        self.get_hex(cc).map(|h| h.height)
+    }
 }
 
 impl Default for MapData {
@@ -75,15 +154,74 @@ impl Default for MapData {
         Self {
             radius:        0,
             hexes:         Vec::new(),
+            seed:          0,
             light_sources: Vec::new(),
+            spawn_points:  Vec::new(),
+            props:         Vec::new(),
             skybox:        SkyboxCompressed::default(),
         }
     }
 }
 
 impl Hex {
-    pub fn new(height: f32, color: RgbByteColor) -> Self {
-        Self { height, color }
+    pub fn new(height: f32, color: RgbByteColor, surface: Surface) -> Self {
+        Self {
+            height,
+            color,
+            surface,
+        }
+    }
+}
+
+impl Surface {
+    pub const ALL: [Surface; 4] = [
+        Surface::Grass,
+        Surface::Ice,
+        Surface::Mud,
+        Surface::BouncePad,
+    ];
+}
+
+impl Default for Surface {
+    #[inline]
+    fn default() -> Self {
+        Surface::Grass
+    }
+}
+
+impl SpawnPoint {
+    #[inline]
+    pub fn new(hex: CubeCoord, facing: HexDir) -> Self {
+        Self { hex, facing }
+    }
+}
+
+impl Prop {
+    #[inline]
+    pub fn new(
+        shape: PropShape,
+        mass: f32,
+        position: [f32; 3],
+        color: RgbByteColor,
+    ) -> Self {
+        Self {
+            shape,
+            mass,
+            position,
+            color,
+        }
+    }
+}
+
+impl PropShape {
+    /// Distance from the center of the shape down to its bottom, when
+    /// unrotated.
+    #[inline]
+    pub fn half_height(&self) -> f32 {
+        match *self {
+            PropShape::Cuboid(half_extents) => half_extents[1],
+            PropShape::Ball(radius) => radius,
+        }
     }
 }
 
@@ -0,0 +1,663 @@
+//! A* pathfinding and reachability analysis over hex maps, where the height
+//! difference between two adjacent hexes both costs extra to traverse and can
+//! make the step impassable altogether.
+
+use hex::{cube_neighbor, CubeCoord, HexDir};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{hash_map::Entry, BinaryHeap, HashMap, HashSet, VecDeque},
+    f32,
+};
+
+/// Anything that can tell the height of the hex at a given coordinate.
+/// Coordinates that are off of the map must yield `None`.
+pub trait HexHeights {
+    fn hex_height(&self, cc: CubeCoord) -> Option<f32>;
+}
+
+/// Which steps between adjacent hexes are allowed, and what they cost.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct StepRule {
+    /// Largest height that can be climbed in a single step.
+    pub max_step_up:   f32,
+    /// Largest height that can be dropped in a single step. Use
+    /// `f32::INFINITY` to allow arbitrarily high falls.
+    pub max_step_down: f32,
+    /// Extra cost of a step, per unit of height difference. Every step
+    /// otherwise costs `1.0`. Must not be negative; see `is_valid`.
+    pub height_cost:   f32,
+}
+
+/// Summary of which parts of a map can be walked to, and from where.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Traversability {
+    /// Sets of hexes that can all be walked between in both directions,
+    /// largest first.
+    pub regions:    Vec<Vec<CubeCoord>>,
+    /// Hexes that can be walked to from the spawn hex.
+    pub reachable:  HashSet<CubeCoord>,
+    /// Hexes that the spawn hex can be walked to from.
+    pub returnable: HashSet<CubeCoord>,
+    /// Total number of hexes that were analyzed.
+    pub total:      usize,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct OpenNode {
+    f:  f32,
+    g:  f32,
+    cc: CubeCoord,
+}
+
+impl StepRule {
+    #[inline]
+    pub fn new(
+        max_step_up: f32,
+        max_step_down: f32,
+        height_cost: f32,
+    ) -> Self {
+        Self {
+            max_step_up,
+            max_step_down,
+            height_cost,
+        }
+    }
+
+    /// The same rule, but for walking backwards, i.e. a step from `a` to `b`
+    /// is allowed by the reversed rule iff a step from `b` to `a` is allowed
+    /// by this one.
+    #[inline]
+    pub fn reversed(&self) -> Self {
+        Self::new(self.max_step_down, self.max_step_up, self.height_cost)
+    }
+
+    /// A rule where climbing and dropping are limited by the same
+    /// `max_step`.
+    #[inline]
+    pub fn symmetric(max_step: f32, height_cost: f32) -> Self {
+        Self::new(max_step, max_step, height_cost)
+    }
+
+    /// Whether `find_path` can be relied on to find the cheapest path under
+    /// this rule, which takes every step costing at least `1.0`, the
+    /// distance that it covers.
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.height_cost >= 0.0
+    }
+
+    /// The cost of stepping from a hex of height `from` to an adjacent hex
+    /// of height `to`, or `None` if the step is not allowed.
+    #[inline]
+    pub fn step_cost(&self, from: f32, to: f32) -> Option<f32> {
+        let dh = to - from;
+        if dh > self.max_step_up || -dh > self.max_step_down {
+            None
+        } else {
+            Some(self.height_cost.mul_add(dh.abs(), 1.0))
+        }
+    }
+}
+
+impl Default for StepRule {
+    #[inline]
+    fn default() -> Self {
+        Self::new(0.5, f32::INFINITY, 0.0)
+    }
+}
+
+impl Traversability {
+    pub fn analyze<M, I>(
+        map: &M,
+        hexes: I,
+        spawn: CubeCoord,
+        rule: &StepRule,
+    ) -> Self
+    where
+        M: HexHeights + ?Sized,
+        I: IntoIterator<Item = CubeCoord>,
+    {
+        let regions = connected_regions(map, hexes, rule);
+        let total = regions.iter().map(|r| r.len()).sum();
+
+        Self {
+            regions,
+            reachable: reachable_from(map, spawn, rule),
+            returnable: reachable_from(map, spawn, &rule.reversed()),
+            total,
+        }
+    }
+
+    /// Number of analyzed hexes that cannot be walked to from the spawn hex.
+    pub fn unreachable_count(&self) -> usize {
+        self.regions
+            .iter()
+            .flat_map(|r| r.iter())
+            .filter(|cc| !self.reachable.contains(cc))
+            .count()
+    }
+
+    #[inline]
+    pub fn unreachable_fraction(&self) -> f32 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.unreachable_count() as f32 / self.total as f32
+        }
+    }
+
+    /// Number of analyzed hexes that cannot be walked to from the spawn hex,
+    /// or that can be but cannot be walked back from, e.g. because getting
+    /// there means dropping off of a cliff.
+    pub fn stranded_count(&self) -> usize {
+        self.regions
+            .iter()
+            .flat_map(|r| r.iter())
+            .filter(|cc| {
+                !(self.reachable.contains(cc) && self.returnable.contains(cc))
+            }).count()
+    }
+
+    #[inline]
+    pub fn stranded_fraction(&self) -> f32 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.stranded_count() as f32 / self.total as f32
+        }
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl Ord for OpenNode {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed, so that `BinaryHeap` pops the cheapest node first. Ties
+        // are broken in favor of the node furthest along its path.
+        other
+            .f
+            .partial_cmp(&self.f)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| {
+                self.g.partial_cmp(&other.g).unwrap_or(Ordering::Equal)
+            })
+    }
+}
+
+impl PartialOrd for OpenNode {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Finds the cheapest path from `start` to `goal` (both inclusive) under the
+/// given `rule`, which must be valid, or `None` if there is no such path.
+pub fn find_path<M: HexHeights + ?Sized>(
+    map: &M,
+    start: CubeCoord,
+    goal: CubeCoord,
+    rule: &StepRule,
+) -> Option<Vec<CubeCoord>> {
+    debug_assert!(rule.is_valid(), "Invalid step rule {:?}", rule);
+    map.hex_height(start)?;
+    map.hex_height(goal)?;
+
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<CubeCoord, (f32, Option<CubeCoord>)> =
+        HashMap::new();
+
+    open.push(OpenNode {
+        f:  start.distance(goal) as f32,
+        g:  0.0,
+        cc: start,
+    });
+    came_from.insert(start, (0.0, None));
+
+    while let Some(OpenNode { g, cc, .. }) = open.pop() {
+        if cc == goal {
+            return Some(reconstruct_path(&came_from, goal));
+        }
+        if g > came_from[&cc].0 {
+            // Stale entry; a cheaper path to `cc` was already expanded.
+            continue;
+        }
+
+        let height = map.hex_height(cc)?;
+        for &dir in &HexDir::ALL {
+            let neighbor = cube_neighbor(cc, dir);
+            let step_cost = match map
+                .hex_height(neighbor)
+                .and_then(|nh| rule.step_cost(height, nh))
+            {
+                Some(c) => c,
+                None => continue,
+            };
+            let new_g = g + step_cost;
+
+            match came_from.entry(neighbor) {
+                Entry::Occupied(mut o) =>
+                    if new_g < o.get().0 {
+                        o.insert((new_g, Some(cc)));
+                    } else {
+                        continue;
+                    },
+                Entry::Vacant(v) => {
+                    v.insert((new_g, Some(cc)));
+                },
+            }
+
+            open.push(OpenNode {
+                f:  new_g + neighbor.distance(goal) as f32,
+                g:  new_g,
+                cc: neighbor,
+            });
+        }
+    }
+
+    None
+}
+
+fn reconstruct_path(
+    came_from: &HashMap<CubeCoord, (f32, Option<CubeCoord>)>,
+    goal: CubeCoord,
+) -> Vec<CubeCoord> {
+    let mut path = vec![goal];
+    let mut curr = goal;
+    while let Some(&(_, Some(prev))) = came_from.get(&curr) {
+        path.push(prev);
+        curr = prev;
+    }
+    path.reverse();
+
+    path
+}
+
+/// Every hex that can be walked to from `start` (including `start` itself,
+/// if it is on the map).
+pub fn reachable_from<M: HexHeights + ?Sized>(
+    map: &M,
+    start: CubeCoord,
+    rule: &StepRule,
+) -> HashSet<CubeCoord> {
+    let mut reachable = HashSet::new();
+    if map.hex_height(start).is_none() {
+        return reachable;
+    }
+
+    let mut queue = VecDeque::new();
+    reachable.insert(start);
+    queue.push_back(start);
+    while let Some(cc) = queue.pop_front() {
+        let height = match map.hex_height(cc) {
+            Some(h) => h,
+            None => continue,
+        };
+
+        for &dir in &HexDir::ALL {
+            let neighbor = cube_neighbor(cc, dir);
+            if reachable.contains(&neighbor) {
+                continue;
+            }
+
+            let passable = map
+                .hex_height(neighbor)
+                .and_then(|nh| rule.step_cost(height, nh))
+                .is_some();
+            if passable {
+                reachable.insert(neighbor);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    reachable
+}
+
+/// Every hex that can be walked to from `start` and then walked back to
+/// `start` from, including `start` itself, if it is on the map.
+pub fn round_trip_from<M: HexHeights + ?Sized>(
+    map: &M,
+    start: CubeCoord,
+    rule: &StepRule,
+) -> HashSet<CubeCoord> {
+    let returnable = reachable_from(map, start, &rule.reversed());
+
+    reachable_from(map, start, rule)
+        .into_iter()
+        .filter(|cc| returnable.contains(cc))
+        .collect()
+}
+
+/// Partitions the given `hexes` into regions, such that two adjacent hexes
+/// belong to the same region iff the step between them is allowed in both
+/// directions. Regions only ever hold hexes from `hexes`, so two of them may
+/// only be connected through hexes that were left out. Hexes that are not
+/// on the map are ignored. Regions are sorted largest first.
+pub fn connected_regions<M, I>(
+    map: &M,
+    hexes: I,
+    rule: &StepRule,
+) -> Vec<Vec<CubeCoord>>
+where
+    M: HexHeights + ?Sized,
+    I: IntoIterator<Item = CubeCoord>,
+{
+    let seeds: Vec<_> = hexes.into_iter().collect();
+    let members: HashSet<_> = seeds.iter().cloned().collect();
+    let mut visited = HashSet::new();
+    let mut regions = Vec::new();
+    let mut queue = VecDeque::new();
+
+    for seed in seeds {
+        if visited.contains(&seed) || map.hex_height(seed).is_none() {
+            continue;
+        }
+
+        let mut region = Vec::new();
+        visited.insert(seed);
+        queue.push_back(seed);
+        while let Some(cc) = queue.pop_front() {
+            region.push(cc);
+            let height = match map.hex_height(cc) {
+                Some(h) => h,
+                None => continue,
+            };
+
+            for &dir in &HexDir::ALL {
+                let neighbor = cube_neighbor(cc, dir);
+                if visited.contains(&neighbor) || !members.contains(&neighbor)
+                {
+                    continue;
+                }
+
+                let mutually_passable = match map.hex_height(neighbor) {
+                    Some(nh) =>
+                        rule.step_cost(height, nh).is_some()
+                            && rule.step_cost(nh, height).is_some(),
+                    None => false,
+                };
+                if mutually_passable {
+                    visited.insert(neighbor);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+
+        regions.push(region);
+    }
+
+    regions.sort_by_key(|r| Reverse(r.len()));
+
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADIUS: usize = 4;
+    const CLIFF: f32 = 10.0;
+
+    /// A hexagonal map of radius `RADIUS` around the origin, flat apart from
+    /// any hexes given heights in `raised`.
+    struct TestMap(HashMap<CubeCoord, f32>);
+
+    impl TestMap {
+        fn new(raised: &[(CubeCoord, f32)]) -> Self {
+            let mut heights: HashMap<_, _> = CubeCoord::origin()
+                .spiral(RADIUS)
+                .map(|cc| (cc, 0.0))
+                .collect();
+            for &(cc, height) in raised {
+                *heights.get_mut(&cc).unwrap() = height;
+            }
+
+            TestMap(heights)
+        }
+
+        fn coords(&self) -> Vec<CubeCoord> {
+            CubeCoord::origin().spiral(RADIUS).collect()
+        }
+    }
+
+    impl HexHeights for TestMap {
+        fn hex_height(&self, cc: CubeCoord) -> Option<f32> {
+            self.0.get(&cc).cloned()
+        }
+    }
+
+    /// Number of steps on the shortest path from `start` to `goal`, found by
+    /// plain breadth-first search.
+    fn fewest_steps(
+        map: &TestMap,
+        start: CubeCoord,
+        goal: CubeCoord,
+        rule: &StepRule,
+    ) -> Option<usize> {
+        let mut steps = HashMap::new();
+        let mut queue = VecDeque::new();
+        steps.insert(start, 0);
+        queue.push_back(start);
+        while let Some(cc) = queue.pop_front() {
+            if cc == goal {
+                return Some(steps[&cc]);
+            }
+            for &dir in &HexDir::ALL {
+                let neighbor = cc.neighbor(dir);
+                let passable = map
+                    .hex_height(neighbor)
+                    .and_then(|nh| rule.step_cost(map.0[&cc], nh))
+                    .is_some();
+                if passable && !steps.contains_key(&neighbor) {
+                    steps.insert(neighbor, steps[&cc] + 1);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+
+        None
+    }
+
+    fn off_map() -> CubeCoord {
+        let r = RADIUS as isize + 1;
+
+        CubeCoord::new(r, 0, -r)
+    }
+
+    fn path_cost(map: &TestMap, path: &[CubeCoord], rule: &StepRule) -> f32 {
+        path.windows(2)
+            .map(|w| {
+                assert_eq!(w[0].distance(w[1]), 1);
+
+                rule.step_cost(map.0[&w[0]], map.0[&w[1]])
+                    .expect("Every step of the path should be allowed")
+            }).sum()
+    }
+
+    #[test]
+    fn step_limits() {
+        let rule = StepRule::new(0.5, 2.0, 1.0);
+        assert_eq!(rule.step_cost(0.0, 0.0), Some(1.0));
+        assert_eq!(rule.step_cost(0.0, 0.5), Some(1.5));
+        assert_eq!(rule.step_cost(0.0, 0.6), None);
+        assert_eq!(rule.step_cost(2.0, 0.0), Some(3.0));
+        assert_eq!(rule.step_cost(2.5, 0.0), None);
+
+        let reversed = rule.reversed();
+        let heights = [-3.0, -0.5, 0.0, 0.25, 0.5, 1.5, 2.0, 4.0];
+        for &a in &heights {
+            for &b in &heights {
+                assert_eq!(reversed.step_cost(a, b), rule.step_cost(b, a));
+            }
+        }
+
+        let symmetric = StepRule::symmetric(1.0, 0.0);
+        assert_eq!(symmetric.step_cost(0.0, 1.0), Some(1.0));
+        assert_eq!(symmetric.step_cost(1.0, 0.0), Some(1.0));
+        assert_eq!(symmetric.step_cost(0.0, 1.5), None);
+        assert_eq!(symmetric.step_cost(1.5, 0.0), None);
+
+        assert!(StepRule::default().is_valid());
+        assert!(!StepRule::new(0.5, 0.5, -1.0).is_valid());
+    }
+
+    #[test]
+    fn path_to_self() {
+        let map = TestMap::new(&[]);
+        let start = CubeCoord::new(1, -2, 1);
+        assert_eq!(
+            find_path(&map, start, start, &StepRule::default()),
+            Some(vec![start]),
+        );
+    }
+
+    #[test]
+    fn straight_path_on_flat_map() {
+        let map = TestMap::new(&[]);
+        let rule = StepRule::default();
+        let coords = map.coords();
+        for &start in coords.iter().step_by(5) {
+            for &goal in &coords {
+                let path = find_path(&map, start, goal, &rule).unwrap();
+                assert_eq!(path.first(), Some(&start));
+                assert_eq!(path.last(), Some(&goal));
+                assert_eq!(path.len(), start.distance(goal) + 1);
+                assert_eq!(
+                    path_cost(&map, &path, &rule),
+                    start.distance(goal) as f32,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn optimal_path_around_cliff() {
+        // A wall across the map, with a gap at its southern end
+        let wall: Vec<_> = (-(RADIUS as isize)..RADIUS as isize)
+            .map(|z| (CubeCoord::new(0, -z, z), CLIFF))
+            .collect();
+        let map = TestMap::new(&wall);
+        let rule = StepRule::symmetric(0.5, 0.0);
+        let start = CubeCoord::new(-2, 2, 0);
+        let goal = CubeCoord::new(2, -2, 0);
+
+        let path = find_path(&map, start, goal, &rule).unwrap();
+        assert_eq!(path.first(), Some(&start));
+        assert_eq!(path.last(), Some(&goal));
+        assert!(path.iter().all(|cc| map.0[cc] == 0.0));
+        assert!(path.contains(&CubeCoord::new(0, -(RADIUS as isize), 4)));
+
+        let fewest = fewest_steps(&map, start, goal, &rule).unwrap();
+        assert!(fewest > start.distance(goal));
+        assert_eq!(path.len(), fewest + 1);
+        assert_eq!(path_cost(&map, &path, &rule), fewest as f32);
+    }
+
+    #[test]
+    fn climbing_costs_extra() {
+        // Along an axis, so that there is only one shortest path
+        let start = CubeCoord::new(-2, 2, 0);
+        let goal = CubeCoord::new(2, -2, 0);
+        let hill = CubeCoord::origin();
+        let map = TestMap::new(&[(hill, 0.4)]);
+
+        // The straight path crosses the hill, which is free to climb
+        let free = StepRule::symmetric(0.5, 0.0);
+        let path = find_path(&map, start, goal, &free).unwrap();
+        assert_eq!(path.len(), start.distance(goal) + 1);
+
+        // But going around is cheaper once climbing costs enough
+        let costly = StepRule::symmetric(0.5, 10.0);
+        let path = find_path(&map, start, goal, &costly).unwrap();
+        assert!(!path.contains(&hill));
+        assert_eq!(path.len(), start.distance(goal) + 2);
+        assert_eq!(
+            path_cost(&map, &path, &costly),
+            (start.distance(goal) + 1) as f32,
+        );
+    }
+
+    #[test]
+    fn walled_in_goal_is_unreachable() {
+        let goal = CubeCoord::new(1, 1, -2);
+        let walls: Vec<_> = goal.ring(1).map(|cc| (cc, CLIFF)).collect();
+        let map = TestMap::new(&walls);
+        let rule = StepRule::symmetric(0.5, 0.0);
+        let start = CubeCoord::new(-3, 0, 3);
+
+        assert_eq!(find_path(&map, start, goal, &rule), None);
+        assert_eq!(find_path(&map, goal, start, &rule), None);
+        assert_eq!(reachable_from(&map, goal, &rule).len(), 1);
+        assert!(!reachable_from(&map, start, &rule).contains(&goal));
+
+        let off_map = off_map();
+        assert_eq!(find_path(&map, start, off_map, &rule), None);
+        assert!(reachable_from(&map, off_map, &rule).is_empty());
+    }
+
+    #[test]
+    fn one_way_drop() {
+        // A plateau that can be dropped off of, but not climbed back onto
+        let plateau: Vec<_> = CubeCoord::origin()
+            .spiral(1)
+            .map(|cc| (cc, 2.0))
+            .collect();
+        let map = TestMap::new(&plateau);
+        let rule = StepRule::new(0.5, f32::INFINITY, 0.0);
+        let top = CubeCoord::origin();
+        let bottom = CubeCoord::new(3, -3, 0);
+        let total = map.coords().len();
+
+        assert!(find_path(&map, top, bottom, &rule).is_some());
+        assert_eq!(find_path(&map, bottom, top, &rule), None);
+
+        assert_eq!(reachable_from(&map, top, &rule).len(), total);
+        let round_trip = round_trip_from(&map, top, &rule);
+        assert_eq!(round_trip.len(), plateau.len());
+        assert!(plateau.iter().all(|(cc, _)| round_trip.contains(cc)));
+        assert_eq!(
+            round_trip_from(&map, bottom, &rule).len(),
+            total - plateau.len(),
+        );
+
+        let from_top =
+            Traversability::analyze(&map, map.coords(), top, &rule);
+        assert_eq!(from_top.total, total);
+        assert_eq!(from_top.regions.len(), 2);
+        assert_eq!(from_top.regions[1].len(), plateau.len());
+        assert_eq!(from_top.unreachable_count(), 0);
+        assert_eq!(from_top.stranded_count(), total - plateau.len());
+
+        let from_bottom =
+            Traversability::analyze(&map, map.coords(), bottom, &rule);
+        assert_eq!(from_bottom.unreachable_count(), plateau.len());
+        assert_eq!(from_bottom.stranded_count(), plateau.len());
+    }
+
+    #[test]
+    fn regions_only_hold_given_hexes() {
+        let map = TestMap::new(&[]);
+        let rule = StepRule::default();
+
+        let regions = connected_regions(&map, map.coords(), &rule);
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].len(), map.coords().len());
+
+        // Two separate lines of hexes, which only connect through hexes
+        // that were left out
+        let long = (0..4).map(|x| CubeCoord::new(x, -x, 0));
+        let short = (0..2).map(|x| CubeCoord::new(x, -x - 2, 2));
+        let off_map = off_map();
+        let hexes: Vec<_> = short.chain(long).chain(Some(off_map)).collect();
+        let regions = connected_regions(&map, hexes.clone(), &rule);
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].len(), 4);
+        assert_eq!(regions[1].len(), 2);
+        for cc in regions.iter().flat_map(|r| r.iter()) {
+            assert!(hexes.contains(cc));
+        }
+        assert!(regions.iter().all(|r| !r.contains(&off_map)));
+    }
+}